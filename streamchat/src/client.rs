@@ -0,0 +1,381 @@
+//! A client for reading events from a `streamchatd` instance
+use crate::filter::Filter;
+use crate::handshake::{self, Handshake, Hello, Welcome};
+use crate::{Encoding, Event, Message, Version};
+use crossbeam_channel as channel;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::Path;
+
+/// A connection to `streamchatd`
+///
+/// Events can be read blocking with [`read_event`](#method.read_event), non-blocking with
+/// [`try_read_event`](#method.try_read_event), by iterating over the client, or from a
+/// channel with [`spawn`](#method.spawn)
+#[derive(Debug)]
+pub struct DaemonClient {
+    stream: Stream,
+    buf: Vec<u8>,
+    disconnected: bool,
+    channels: Vec<String>,
+    encoding: Encoding,
+    events: Vec<String>,
+    filter: Option<Filter>,
+    resume: Option<u64>,
+    handshake: Handshake,
+    welcome: Option<Welcome>,
+    last_seq: Option<u64>,
+}
+
+impl DaemonClient {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        TcpStream::connect(addr)
+            .map(Self::from_stream)
+            .map_err(Error::Io)
+    }
+
+    /// Connects to the daemon's Unix socket at `path`
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<Path>) -> Result<Self, Error> {
+        UnixStream::connect(path)
+            .map(Self::from_unix_stream)
+            .map_err(Error::Io)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> Self {
+        Self::new(Stream::Tcp(stream))
+    }
+
+    #[cfg(unix)]
+    pub fn from_unix_stream(stream: UnixStream) -> Self {
+        Self::new(Stream::Unix(stream))
+    }
+
+    fn new(stream: Stream) -> Self {
+        Self {
+            stream,
+            buf: vec![],
+            disconnected: false,
+            channels: vec![],
+            encoding: Encoding::Json,
+            events: vec![],
+            filter: None,
+            resume: None,
+            handshake: Handshake::Hello,
+            welcome: None,
+            last_seq: None,
+        }
+    }
+
+    /// Only receive events from these channels, instead of all of the channels the daemon is in.
+    ///
+    /// The daemon is asked to only send these, and they are checked here too for daemons older
+    /// than the [handshake](../handshake/index.html). Whispers aren't sent to a channel, so they
+    /// are always received
+    pub fn subscribe(mut self, channels: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.channels = channels
+            .into_iter()
+            .map(|channel| crate::normalize_channel(channel.as_ref()))
+            .collect();
+        self
+    }
+
+    /// The encoding the daemon writes on this connection. The default is JSON, see
+    /// [`encoding`](../encoding/index.html).
+    ///
+    /// It is asked for in the hello, daemons older than the [handshake](../handshake/index.html)
+    /// only write the encoding of the socket
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Only receive these events, by the name in their `event` field (e.g. `"message"`).
+    ///
+    /// The daemon is asked to only send these, and they are checked here too for daemons
+    /// older than the [handshake](../handshake/index.html)
+    pub fn event_types(mut self, events: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.events = events
+            .into_iter()
+            .map(|event| event.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// Only receive the events matching `filter`, checked like [`event_types`](#method.event_types)
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Asks the daemon to only send the events after the sequence id `seq`, e.g. the
+    /// [`last_seq`](#method.last_seq) of an earlier connection.
+    ///
+    /// If some of those are gone, an [`Event::Gap`](../event/enum.Event.html#variant.Gap) is
+    /// sent first. Without this the whole backlog is sent
+    pub fn resume(mut self, seq: u64) -> Self {
+        self.resume = Some(seq);
+        self
+    }
+
+    /// The sequence id of the last event read, if the daemon sent one
+    pub fn last_seq(&self) -> Option<u64> {
+        self.last_seq
+    }
+
+    /// What the daemon picked for this connection. This is known once the first event is
+    /// read, and is `None` for daemons older than the [handshake](../handshake/index.html)
+    pub fn welcome(&self) -> Option<&Welcome> {
+        self.welcome.as_ref()
+    }
+
+    /// Blocks until the next event arrives
+    pub fn read_event(&mut self) -> Result<Event, Error> {
+        self.send_hello()?;
+        self.stream.set_nonblocking(false)?;
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                if let Some(event) = self.decode(&frame)? {
+                    return Ok(event);
+                }
+            }
+        }
+    }
+
+    /// Returns the next event if a complete one has arrived, otherwise `Ok(None)`
+    pub fn try_read_event(&mut self) -> Result<Option<Event>, Error> {
+        self.send_hello()?;
+        self.stream.set_nonblocking(true)?;
+        while let Some(frame) = self.next_frame()? {
+            if let Some(event) = self.decode(&frame)? {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Only the chat messages, skipping the other events
+    pub fn messages(self) -> impl Iterator<Item = Result<Message, Error>> {
+        self.filter_map(|event| match event {
+            Ok(Event::Message(msg)) => Some(Ok(msg)),
+            Ok(..) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    /// Reads on a new thread, sending events to the returned channel.
+    ///
+    /// Malformed lines and unknown versions are logged and skipped. The thread ends when the
+    /// daemon disconnects, the receiver is dropped or the connection errors
+    pub fn spawn(
+        self,
+    ) -> (
+        channel::Receiver<Event>,
+        thread::JoinHandle<Result<(), Error>>,
+    ) {
+        let (tx, rx) = channel::unbounded();
+        let handle = thread::spawn(move || {
+            let mut client = self;
+            loop {
+                match client.read_event() {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            return Ok(());
+                        }
+                    }
+                    Err(Error::Disconnected) => return Ok(()),
+                    Err(err) if err.is_recoverable() => log::warn!("skipping line: {}", err),
+                    Err(err) => return Err(err),
+                }
+            }
+        });
+        (rx, handle)
+    }
+
+    fn hello(&self) -> Hello {
+        Hello {
+            encoding: Some(self.encoding),
+            channels: self.channels.clone(),
+            events: self.events.clone(),
+            filter: self.filter.clone(),
+            resume: self.resume,
+            ..Hello::new()
+        }
+    }
+
+    fn send_hello(&mut self) -> Result<(), Error> {
+        if self.handshake == Handshake::Hello {
+            self.stream.set_nonblocking(false)?;
+            self.stream.write_all(&self.hello().to_line())?;
+            self.handshake = Handshake::Reply;
+        }
+        Ok(())
+    }
+
+    // None for the reply to the hello, and the events that weren't asked for
+    fn decode(&mut self, frame: &[u8]) -> Result<Option<Event>, Error> {
+        if self.handshake == Handshake::Reply {
+            self.handshake = Handshake::Done;
+            if let Some(welcome) = handshake::read_reply(self.encoding, frame)? {
+                self.encoding = welcome.encoding;
+                self.welcome = Some(welcome);
+                return Ok(None);
+            }
+        }
+
+        let (seq, event) = self.encoding.decode_seq(frame)?;
+        if seq.is_some() {
+            self.last_seq = seq;
+        }
+        let wanted =
+            handshake::is_wanted(&self.channels, &self.events, self.filter.as_ref(), &event);
+        Ok(Some(event).filter(|_| wanted))
+    }
+
+    // Ok(None) is returned when a non-blocking read would block
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            if let Some(frame) = self.encoding.next_frame(&mut self.buf)? {
+                return Ok(Some(frame));
+            }
+
+            let mut buf = [0; 4096];
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.disconnected = true;
+                    return Err(Error::Disconnected);
+                }
+                Ok(n) => self.buf.extend_from_slice(&buf[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Yields events until the daemon disconnects, or a non-recoverable error happens
+impl Iterator for DaemonClient {
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.disconnected {
+            return None;
+        }
+        match self.read_event() {
+            Err(Error::Disconnected) => None,
+            Err(err) => {
+                self.disconnected = !err.is_recoverable();
+                Some(Err(err))
+            }
+            Ok(event) => Some(Ok(event)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The line wasn't a valid event
+    Malformed {
+        line: String,
+        error: serde_json::Error,
+    },
+    /// The frame wasn't a valid MessagePack event
+    Undecodable(rmp_serde::decode::Error),
+    /// The message is from a newer version than this client understands
+    UnsupportedVersion(Version),
+    /// The daemon didn't accept the hello, with its reason
+    Rejected(String),
+    Disconnected,
+}
+
+impl Error {
+    /// Whether the client can keep reading after this error
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            Error::Malformed { .. } | Error::Undecodable(..) | Error::UnsupportedVersion(..)
+        )
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "io error: {}", err),
+            Error::Malformed { line, error } => write!(f, "malformed line `{}`: {}", line, error),
+            Error::Undecodable(err) => write!(f, "malformed frame: {}", err),
+            Error::UnsupportedVersion(Version(ver)) => write!(f, "unsupported version: {}", ver),
+            Error::Rejected(reason) => write!(f, "rejected by the daemon: {}", reason),
+            Error::Disconnected => write!(f, "disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Malformed { error, .. } => Some(error),
+            Error::Undecodable(err) => Some(err),
+            _ => None,
+        }
+    }
+}
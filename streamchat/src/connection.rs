@@ -1,66 +1,148 @@
+use crate::rng::Rng;
 use crate::twitch;
 use crossbeam_channel as channel;
-use std::net::TcpStream;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
 
-// TODO pass in the reader so we can monomorphize
+/// The password Twitch expects for anonymous logins
+pub const ANONYMOUS_TOKEN: &str = "SCHMOOPIIE";
+
+/// Whether connecting with `token` logs in anonymously.
+///
+/// Without a token a random `justinfanNNNNN` nick is used instead. Twitch lets these read chat,
+/// but anything they send is ignored
+pub fn is_anonymous(token: impl AsRef<str>) -> bool {
+    token.as_ref().trim().is_empty()
+}
+
+/// A random nick for an anonymous login
+pub fn anonymous_nick() -> String {
+    format!("justinfan{}", Rng::from_time().range(1000, 100_000))
+}
+
+/// Connects to Twitch, registers with `nick` and `token` then joins each of `channels`.
+///
+/// If `token` is empty this logs in [anonymously](./fn.is_anonymous.html) and `nick` is ignored
 pub fn connect_to_twitch(
     nick: impl AsRef<str>,
     token: impl AsRef<str>,
-    channel: impl AsRef<str>,
+    channels: &[impl AsRef<str>],
 ) -> Result<twitch::Client<twitch::SyncReadAdapter<TcpStream>>, Error> {
-    let nick = nick.as_ref();
-    let token = token.as_ref();
-    let channel = channel.as_ref();
+    connect_to(twitch::TWITCH_IRC_ADDRESS, nick, token, channels)
+}
 
-    if nick.is_empty() {
-        return Err(Error::InvalidNick)?;
-    }
-    if token.is_empty() {
-        return Err(Error::InvalidToken)?;
-    }
-    if channel.is_empty() {
-        return Err(Error::InvalidChannel)?;
-    }
+/// Like [`connect_to_twitch`](./fn.connect_to_twitch.html) but connects to `addr` instead of
+/// Twitch's IRC server
+pub fn connect_to(
+    addr: impl ToSocketAddrs + std::fmt::Debug,
+    nick: impl AsRef<str>,
+    token: impl AsRef<str>,
+    channels: &[impl AsRef<str>],
+) -> Result<twitch::Client<twitch::SyncReadAdapter<TcpStream>>, Error> {
+    validate(nick.as_ref(), token.as_ref(), channels)?;
 
-    log::info!("connecting to: {}", twitch::TWITCH_IRC_ADDRESS);
+    log::info!("connecting to: {:?}", addr);
     let (read, write) = {
-        let read = TcpStream::connect(twitch::TWITCH_IRC_ADDRESS)?;
-        let write = read.try_clone().expect("clone tcpstream");
+        let read = TcpStream::connect(addr)?;
+        let write = read.try_clone()?;
         (read, write)
     };
     log::info!("opened connection");
 
+    connect_with(read, write, nick, token, channels)
+}
+
+/// Registers and joins `channels` over an already opened `read`/`write` pair
+///
+/// This can be used for proxied or TLS streams, or a recorded session
+pub fn connect_with<R, W>(
+    read: R,
+    write: W,
+    nick: impl AsRef<str>,
+    token: impl AsRef<str>,
+    channels: &[impl AsRef<str>],
+) -> Result<twitch::Client<twitch::SyncReadAdapter<R>>, Error>
+where
+    R: Read,
+    W: Write + Send + 'static,
+{
+    validate(nick.as_ref(), token.as_ref(), channels)?;
+
+    let anonymous = is_anonymous(&token);
+    let (nick, token) = if anonymous {
+        (anonymous_nick(), ANONYMOUS_TOKEN)
+    } else {
+        (nick.as_ref().to_string(), token.as_ref())
+    };
+
     let (read, write) = twitch::sync_adapters(read, write);
     let mut client = twitch::Client::new(read, write);
 
     let conf = twitch::UserConfig::builder()
-        .nick(nick)
+        .nick(&nick)
         .token(token)
         .tags()
         .commands()
+        // JOIN and PART for other users, for `Event::Join` and `Event::Part`
+        .membership()
         .build()
         .expect("valid configuration");
     log::info!("registering with nick: {}", conf.nick);
     client.register(conf)?;
 
-    let user = match client.wait_for_ready() {
-        Ok(user) => user,
-        Err(twitch::Error::InvalidRegistration) => return Err(Error::InvalidLogin),
-        Err(err) => return Err(err.into()),
-    };
+    // twitch doesn't send a GLOBALUSERSTATE to anonymous users, so there is nothing to wait for
+    if anonymous {
+        log::warn!("connected anonymously as {}. sending is disabled", nick);
+    } else {
+        let user = match client.wait_for_ready() {
+            Ok(user) => user,
+            Err(twitch::Error::InvalidRegistration) => return Err(Error::InvalidLogin),
+            Err(err) => return Err(err.into()),
+        };
 
-    log::info!(
-        "connected with {} ({}).",
-        user.display_name.expect("get our display name"),
-        user.user_id
-    );
+        log::info!(
+            "connected with {} ({}).",
+            user.display_name.expect("get our display name"),
+            user.user_id
+        );
+    }
 
-    client.writer().join(&channel)?;
-    log::info!("joined: {}", channel.to_string());
+    for channel in channels {
+        let channel = crate::normalize_channel(channel.as_ref());
+        client.writer().join(format!("#{}", channel))?;
+        log::info!("joined: {}", channel);
+    }
 
     Ok(client)
 }
 
+pub(crate) fn validate(nick: &str, token: &str, channels: &[impl AsRef<str>]) -> Result<(), Error> {
+    // anonymous logins pick their own nick
+    if is_anonymous(token) {
+        return validate_channels(channels);
+    }
+    if nick.is_empty() {
+        return Err(Error::InvalidNick);
+    }
+    if !token.starts_with("oauth:") {
+        return Err(Error::InvalidToken);
+    }
+    validate_channels(channels)
+}
+
+fn validate_channels(channels: &[impl AsRef<str>]) -> Result<(), Error> {
+    if channels.is_empty()
+        || channels
+            .iter()
+            .any(|c| crate::normalize_channel(c.as_ref()).is_empty())
+    {
+        return Err(Error::InvalidChannel);
+    }
+    Ok(())
+}
+
 pub fn read_until_end<R>(
     client: twitch::Client<R>,
     send: channel::Sender<crate::Message>,
@@ -75,7 +157,13 @@ where
             _ => continue,
         };
 
-        let msg = msg.into();
+        let msg = match crate::Message::try_from(msg) {
+            Ok(msg) => msg,
+            Err(err) => {
+                log::warn!("skipping message: {}", err);
+                continue;
+            }
+        };
         if send.send(msg).is_err() {
             break;
         }
@@ -84,6 +172,218 @@ where
     Err(Error::Disconnected)
 }
 
+/// Like [`read_until_end`](./fn.read_until_end.html) but sends every chat
+/// [`Event`](../enum.Event.html), not just messages
+pub fn read_events_until_end<R>(
+    client: twitch::Client<R>,
+    send: channel::Sender<crate::Event>,
+) -> Result<(), Error>
+where
+    R: twitch::ReadAdapter + Send + Sync,
+{
+    let mut client = client;
+    loop {
+        let event = match crate::Event::from_twitch(client.read_message()?) {
+            Some(event) => event,
+            None => continue,
+        };
+
+        if send.send(event).is_err() {
+            break;
+        }
+    }
+
+    Err(Error::Disconnected)
+}
+
+/// Exponential backoff, with jitter, used between reconnect attempts
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Delay before the first retry
+    pub initial: Duration,
+    /// Upper bound for any single delay
+    pub max: Duration,
+    /// Multiplier applied for each consecutive failure
+    pub factor: u32,
+    /// Give up after this many consecutive failures. `None` retries forever
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(60),
+            factor: 2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay for the nth (1-based) consecutive failure.
+    ///
+    /// The delay is picked randomly from the upper half of the exponential window
+    pub(crate) fn delay(&self, attempt: u32, rng: &mut Rng) -> Duration {
+        let exp = (0..attempt.saturating_sub(1)).fold(self.initial, |d, _| {
+            d.checked_mul(self.factor).unwrap_or(self.max).min(self.max)
+        });
+        let ms = exp.as_millis() as u64;
+        Duration::from_millis(rng.range(ms / 2, ms + 1))
+    }
+
+    fn exhausted(&self, attempt: u32) -> bool {
+        self.max_attempts.map(|max| attempt >= max).unwrap_or(false)
+    }
+}
+
+/// Lifecycle notifications from a [`Supervisor`](./struct.Supervisor.html)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    /// Trying to connect, `attempt` is 1-based and resets after a successful connection
+    Connecting { attempt: u32 },
+    /// Registered and joined the channels. Nothing can be sent on an `anonymous` connection
+    Connected { anonymous: bool },
+    /// Waiting `delay` before the next attempt
+    BackingOff {
+        attempt: u32,
+        delay: Duration,
+        reason: String,
+    },
+    /// Stopped retrying
+    GaveUp { reason: String },
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Connecting { attempt } => write!(f, "connecting (attempt #{})", attempt),
+            Status::Connected { anonymous: false } => write!(f, "connected"),
+            Status::Connected { anonymous: true } => {
+                write!(f, "connected anonymously, sending is disabled")
+            }
+            Status::BackingOff {
+                attempt,
+                delay,
+                reason,
+            } => write!(
+                f,
+                "attempt #{} failed: {}. retrying in {:.1}s",
+                attempt,
+                reason,
+                delay.as_millis() as f64 / 1000.0
+            ),
+            Status::GaveUp { reason } => write!(f, "gave up: {}", reason),
+        }
+    }
+}
+
+/// Keeps a connection to Twitch alive, reconnecting with a [`Backoff`](./struct.Backoff.html)
+/// whenever it drops
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    address: String,
+    nick: String,
+    token: String,
+    channels: Vec<String>,
+    backoff: Backoff,
+}
+
+impl Supervisor {
+    pub fn new(
+        nick: impl ToString,
+        token: impl ToString,
+        channels: impl IntoIterator<Item = impl ToString>,
+    ) -> Self {
+        Self {
+            address: twitch::TWITCH_IRC_ADDRESS.to_string(),
+            nick: nick.to_string(),
+            token: token.to_string(),
+            channels: channels.into_iter().map(|c| c.to_string()).collect(),
+            backoff: Backoff::default(),
+        }
+    }
+
+    /// Connect to `address` instead of Twitch's IRC server
+    pub fn address(mut self, address: impl ToString) -> Self {
+        self.address = address.to_string();
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Connects, registers and joins, then forwards chat events to `send`.
+    ///
+    /// Lifecycle changes are sent to `status`, it's fine if nothing is listening on it.
+    ///
+    /// This returns `Ok(())` once the receiving side of `send` is dropped, or an error if
+    /// the login was rejected or the backoff gave up
+    pub fn run(
+        self,
+        send: channel::Sender<crate::Event>,
+        status: channel::Sender<Status>,
+    ) -> Result<(), Error> {
+        self.run_with(send, status, |_| {})
+    }
+
+    // like `run`, giving `connected` the writer of each new connection
+    pub(crate) fn run_with<F>(
+        self,
+        send: channel::Sender<crate::Event>,
+        status: channel::Sender<Status>,
+        mut connected: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(twitch::Writer),
+    {
+        let mut rng = Rng::from_time();
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let _ = status.send(Status::Connecting { attempt });
+
+            let err = match connect_to(&self.address, &self.nick, &self.token, &self.channels) {
+                Ok(client) => {
+                    attempt = 0;
+                    connected(client.writer());
+                    let _ = status.send(Status::Connected {
+                        anonymous: is_anonymous(&self.token),
+                    });
+                    match read_events_until_end(client, send.clone()) {
+                        // the receiver hung up, so nobody wants anything else
+                        Err(Error::Disconnected) | Ok(..) => return Ok(()),
+                        Err(err) => err,
+                    }
+                }
+                Err(err) => err,
+            };
+
+            if err.is_fatal() || self.backoff.exhausted(attempt) {
+                log::error!("giving up on connection: {}", err);
+                let _ = status.send(Status::GaveUp {
+                    reason: err.to_string(),
+                });
+                return Err(err);
+            }
+
+            // a dropped connection counts as the first failure
+            let attempt = attempt.max(1);
+            let delay = self.backoff.delay(attempt, &mut rng);
+            log::warn!("connection failed: {}. retrying in {:?}", err, delay);
+            let _ = status.send(Status::BackingOff {
+                attempt,
+                delay,
+                reason: err.to_string(),
+            });
+            std::thread::sleep(delay);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Twitch(twitch::Error),
@@ -93,6 +393,18 @@ pub enum Error {
     InvalidNick,
     InvalidToken,
     InvalidChannel,
+    /// Tried to send on an anonymous connection
+    ReadOnly,
+}
+
+impl Error {
+    /// Whether retrying the connection could never succeed
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Error::InvalidLogin | Error::InvalidNick | Error::InvalidToken | Error::InvalidChannel
+        )
+    }
 }
 
 impl From<twitch::Error> for Error {
@@ -117,6 +429,7 @@ impl std::fmt::Display for Error {
             Error::InvalidNick => write!(f, "invalid nick"),
             Error::InvalidToken => write!(f, "invalid token"),
             Error::InvalidChannel => write!(f, "invalid channel"),
+            Error::ReadOnly => write!(f, "cannot send on an anonymous connection"),
         }
     }
 }
@@ -0,0 +1,54 @@
+use crate::{Message, Role};
+use std::collections::HashMap;
+
+pub(crate) enum Response {
+    Message(String),
+    Nothing,
+    Missing,
+    /// The user's role is lower than the command requires
+    Denied,
+}
+
+type Func = Box<dyn Fn(&Message, &str) -> Option<String>>;
+
+#[derive(Default)]
+pub(crate) struct CommandProcessor(HashMap<String, (Role, Func)>);
+
+impl CommandProcessor {
+    /// Adds a command that everyone can use, see [`require`](#method.require)
+    pub(crate) fn add<S, F>(&mut self, command: S, func: F)
+    where
+        S: AsRef<str>,
+        F: Fn(&Message, &str) -> Option<String> + 'static,
+    {
+        self.0.insert(
+            format!("!{}", command.as_ref().trim_start_matches('!')),
+            (Role::Everyone, Box::new(func)),
+        );
+    }
+
+    /// Changes the role a command requires. Returns false if there is no such command
+    pub(crate) fn require(&mut self, command: &str, role: Role) -> bool {
+        let command = format!("!{}", command.trim_start_matches('!'));
+        match self.0.get_mut(&command) {
+            Some((required, _)) => {
+                *required = role;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn handle(&self, msg: &Message, command: &str, rest: &str) -> Response {
+        let func = match self.0.get(command) {
+            Some((required, _)) if msg.role() < *required => return Response::Denied,
+            Some((_, func)) => func,
+            None => return Response::Missing,
+        };
+
+        match (func)(msg, rest) {
+            Some(msg) => Response::Message(msg),
+            None => Response::Nothing,
+        }
+    }
+}
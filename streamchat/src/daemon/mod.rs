@@ -0,0 +1,331 @@
+//! The streamchatd service, for running it inside another process
+//!
+//! A [`Builder`](./struct.Builder.html) keeps a connection to Twitch with a
+//! [`Supervisor`](../connection/struct.Supervisor.html), turns what it reads into
+//! [`Event`](../event/enum.Event.html)s and hands each one to every
+//! [`Transport`](../trait.Transport.html). Messages starting with `!` are checked against the
+//! registered commands, and the replies are sent back to the channel through an
+//! [`Outbound`](../outbound/struct.Outbound.html).
+//!
+//! ```text
+//! Builder::new(config)
+//!     .transport(Socket::start("localhost:51002", 32, Encoding::Json)?)
+//!     .command("hello", |msg, _args| Some(format!("hello {}", msg.name)))
+//!     .require("hello", Role::Subscriber)
+//!     .run()
+//! ```
+//!
+//! Commands are disabled when connecting anonymously, as those connections can't send anything
+use crate::connection::{self, Backoff, Status, Supervisor};
+use crate::event::Gap;
+use crate::{outbound, Color, Message, Queue, Role, Transport};
+use crossbeam_channel as channel;
+
+mod commands;
+use commands::CommandProcessor;
+
+mod service;
+use service::{Connection, Service};
+
+mod socket;
+pub use self::socket::Socket;
+#[cfg(unix)]
+pub use self::socket::UnixOptions;
+
+mod sse;
+pub use self::sse::EventStream;
+
+#[cfg(feature = "websocket")]
+mod websocket;
+#[cfg(feature = "websocket")]
+pub use self::websocket::WebSocket;
+
+type ColorFn = Box<dyn Fn(&Message) -> Option<Color>>;
+
+// where a client resuming after `after` starts, and the gap to report if it missed some.
+// `evicted` is the newest id that was dropped from the backlog
+fn resume_point<T>(queue: &Queue<T>, evicted: u64, after: u64) -> (u64, Option<Gap>) {
+    let next = queue.first_seq().unwrap_or_else(|| queue.last_seq() + 1);
+    if after > queue.last_seq() {
+        // the numbering started again, so this is from before a restart
+        return (0, Some(Gap { after, next }));
+    }
+    if after < evicted {
+        return (after, Some(Gap { after, next }));
+    }
+    (after, None)
+}
+
+/// What to connect to Twitch as
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub nick: String,
+    /// Leave this empty to read [anonymously](../connection/fn.is_anonymous.html)
+    pub oauth_token: String,
+    pub channels: Vec<String>,
+}
+
+/// Sets up and runs the daemon
+pub struct Builder {
+    config: Config,
+    transports: Vec<Box<dyn Transport>>,
+    processor: CommandProcessor,
+    custom_color: Option<ColorFn>,
+    outbound: outbound::Config,
+    address: Option<String>,
+    backoff: Backoff,
+    status: Option<channel::Sender<Status>>,
+}
+
+impl Builder {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            transports: vec![],
+            processor: CommandProcessor::default(),
+            custom_color: None,
+            outbound: outbound::Config::default(),
+            address: None,
+            backoff: Backoff::default(),
+            status: None,
+        }
+    }
+
+    /// Adds a transport. Every event is sent to all of them
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transports.push(Box::new(transport));
+        self
+    }
+
+    /// Adds an already boxed transport
+    pub fn boxed_transport(mut self, transport: Box<dyn Transport>) -> Self {
+        self.transports.push(transport);
+        self
+    }
+
+    /// Adds the command `!name`, which everyone can use.
+    ///
+    /// `func` gets the message and everything after the command. If it returns a string, that is
+    /// sent back to the channel
+    pub fn command<F>(mut self, name: impl AsRef<str>, func: F) -> Self
+    where
+        F: Fn(&Message, &str) -> Option<String> + 'static,
+    {
+        self.processor.add(name, func);
+        self
+    }
+
+    /// Requires at least `role` for the command `name`. Unknown commands are logged and ignored
+    pub fn require(mut self, name: impl AsRef<str>, role: Role) -> Self {
+        if !self.processor.require(name.as_ref(), role) {
+            log::warn!(
+                "cannot require a role for unknown command: {}",
+                name.as_ref()
+            );
+        }
+        self
+    }
+
+    /// Sets each message's `custom_color` to what `func` returns for it
+    pub fn custom_color<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&Message) -> Option<Color> + 'static,
+    {
+        self.custom_color = Some(Box::new(func));
+        self
+    }
+
+    /// Changes the rate limits for the replies to commands
+    pub fn outbound(mut self, config: outbound::Config) -> Self {
+        self.outbound = config;
+        self
+    }
+
+    /// Connect to `address` instead of Twitch's IRC server
+    pub fn address(mut self, address: impl ToString) -> Self {
+        self.address = Some(address.to_string());
+        self
+    }
+
+    /// Changes how long to wait between reconnecting
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sends the connection's [`Status`](../connection/enum.Status.html) to `status` whenever it
+    /// changes. It's fine if nothing is listening on it
+    pub fn status(mut self, status: channel::Sender<Status>) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Connects and runs, reconnecting whenever the connection drops.
+    ///
+    /// This returns once the login was rejected or the backoff gave up
+    pub fn run(self) -> Result<(), Error> {
+        let Self {
+            config,
+            transports,
+            processor,
+            custom_color,
+            outbound,
+            address,
+            backoff,
+            status,
+        } = self;
+
+        let anonymous = connection::is_anonymous(&config.oauth_token);
+        if anonymous {
+            log::warn!("no oauth_token is configured, reading anonymously");
+            log::warn!("sending and commands are disabled");
+        }
+
+        let mut supervisor =
+            Supervisor::new(&config.nick, &config.oauth_token, &config.channels).backoff(backoff);
+        if let Some(address) = address {
+            supervisor = supervisor.address(address);
+        }
+
+        let conn = Connection::default();
+        let (processor, outbound) = if anonymous {
+            (None, None)
+        } else {
+            let outbound = outbound::Outbound::start(conn.clone(), outbound);
+            // the broadcaster gets the higher limit in their own channel
+            let own = |ch: &String| {
+                ch.trim_start_matches('#')
+                    .eq_ignore_ascii_case(&config.nick)
+            };
+            if config.channels.iter().any(own) {
+                outbound.set_elevated(&config.nick, true);
+            }
+            (Some(processor), Some(outbound))
+        };
+
+        let (tx, events) = channel::unbounded();
+        // without a listener the statuses are dropped
+        let status = status.unwrap_or_else(|| channel::bounded(0).0);
+        let handle =
+            std::thread::spawn(move || supervisor.run_with(tx, status, |writer| conn.set(writer)));
+
+        // this ends when the supervisor does
+        Service {
+            events,
+            transports,
+            processor,
+            outbound,
+            custom_color,
+            seq: 0,
+        }
+        .run();
+
+        handle
+            .join()
+            .expect("supervisor shouldn't panic")
+            .map_err(Error::Connect)
+    }
+}
+
+impl std::fmt::Debug for Builder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Builder")
+            .field("config", &self.config)
+            .field("transports", &self.transports.len())
+            .field("outbound", &self.outbound)
+            .field("address", &self.address)
+            .field("backoff", &self.backoff)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Couldn't connect to Twitch, and gave up reconnecting
+    Connect(connection::Error),
+}
+
+impl Error {
+    /// Whether running again could never succeed, e.g. the login is invalid
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Error::Connect(err) => err.is_fatal(),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Connect(err) => write!(f, "cannot connect: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Connect(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(seqs: std::ops::RangeInclusive<u64>, size: usize) -> Queue<u64> {
+        let mut queue = Queue::new(size);
+        for seq in seqs {
+            queue.push_with_seq(seq, seq);
+        }
+        queue
+    }
+
+    #[test]
+    fn resume_inside_the_backlog() {
+        let queue = queue(1..=10, 20);
+        assert_eq!(resume_point(&queue, 0, 4), (4, None));
+        assert_eq!(resume_point(&queue, 0, 10), (10, None));
+        assert_eq!(resume_point(&queue, 0, 0), (0, None));
+    }
+
+    #[test]
+    fn resume_after_evicted_events() {
+        // 1 to 5 were evicted
+        let queue = queue(1..=10, 5);
+        assert_eq!(queue.evicted_seq(), 5);
+        assert_eq!(
+            resume_point(&queue, 5, 2),
+            (2, Some(Gap { after: 2, next: 6 }))
+        );
+        assert_eq!(resume_point(&queue, 5, 5), (5, None));
+    }
+
+    #[test]
+    fn resume_from_before_a_restart() {
+        let queue = queue(1..=3, 5);
+        assert_eq!(
+            resume_point(&queue, 0, 500),
+            (
+                0,
+                Some(Gap {
+                    after: 500,
+                    next: 1
+                })
+            )
+        );
+
+        let empty = Queue::<u64>::new(5);
+        assert_eq!(
+            resume_point(&empty, 0, 500),
+            (
+                0,
+                Some(Gap {
+                    after: 500,
+                    next: 1
+                })
+            )
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use super::commands::{CommandProcessor, Response};
+use super::ColorFn;
+use crate::outbound::{Outbound, Sink};
+use crate::twitch::Writer;
+use crate::{Event, Message, Transport};
+use crossbeam_channel as channel;
+use std::sync::{Arc, Mutex};
+
+pub(crate) struct Service {
+    // the events from the supervisor, this ends when it gives up
+    pub(crate) events: channel::Receiver<Event>,
+    pub(crate) transports: Vec<Box<dyn Transport>>,
+    // anonymous connections can't reply, so there are no commands
+    pub(crate) processor: Option<CommandProcessor>,
+    pub(crate) outbound: Option<Outbound>,
+    pub(crate) custom_color: Option<ColorFn>,
+    // the sequence id of the last event, see `Transport::send_sequenced`
+    pub(crate) seq: u64,
+}
+
+impl Service {
+    pub(crate) fn run(mut self) {
+        while let Ok(event) = self.events.recv() {
+            let mut msg = match event {
+                Event::Message(msg) => msg,
+                event => {
+                    self.dispatch(event);
+                    continue;
+                }
+            };
+            log::trace!("got a message");
+
+            if !msg.is_action && msg.data.starts_with('!') {
+                let mut s = msg.data.splitn(2, ' ');
+                if let Some(cmd) = s.next() {
+                    let args = s.next().unwrap_or_default();
+                    self.handle_command(&msg, cmd, args)
+                }
+            }
+
+            if let Some(custom_color) = &self.custom_color {
+                msg.custom_color = custom_color(&msg);
+            }
+            self.dispatch(msg.into());
+        }
+    }
+
+    fn dispatch(&mut self, event: Event) {
+        self.seq += 1;
+        for transport in self.transports.iter_mut() {
+            log::trace!("sending to a transport");
+
+            if let Err(err) = transport.send_sequenced(self.seq, event.clone()) {
+                log::error!("cannot write to transport: {}", err);
+            }
+        }
+    }
+
+    fn handle_command(&mut self, msg: &Message, cmd: &str, args: &str) {
+        let (processor, outbound) = match (&self.processor, &self.outbound) {
+            (Some(processor), Some(outbound)) => (processor, outbound),
+            _ => return,
+        };
+
+        match processor.handle(msg, cmd, args) {
+            Response::Nothing | Response::Missing => {}
+            Response::Denied => log::debug!("{} needs a higher role than {}", cmd, msg.role()),
+            Response::Message(resp) => {
+                // failures are logged by the sender
+                let _ = outbound.send(&msg.channel, resp);
+            }
+        };
+    }
+}
+
+// the writer of the current connection, replaced by the supervisor when it reconnects
+#[derive(Clone, Default)]
+pub(crate) struct Connection(Arc<Mutex<Option<Writer>>>);
+
+impl Connection {
+    pub(crate) fn set(&self, writer: Writer) {
+        *self.0.lock().unwrap() = Some(writer);
+    }
+}
+
+impl Sink for Connection {
+    fn send(&mut self, channel: &str, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match &mut *self.0.lock().unwrap() {
+            Some(writer) => Sink::send(writer, channel, data),
+            None => Err("not connected".into()),
+        }
+    }
+}
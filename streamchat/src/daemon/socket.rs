@@ -0,0 +1,511 @@
+use std::io::{self, prelude::*};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+
+use super::resume_point;
+use crate::handshake::{self, Hello, Reply, Welcome};
+use crate::{Encoding, Event, Message, Queue, Transport, Version};
+use crossbeam_channel as channel;
+
+// how long a new client has to send a hello, before it gets the whole backlog
+const HELLO_WAIT: Duration = Duration::from_millis(250);
+// how often new clients are checked for a hello
+const PENDING_POLL: Duration = Duration::from_millis(10);
+// the hello is a single line
+const MAX_HELLO: usize = 4096;
+
+/// Sends the events to every client connected over TCP or a Unix socket, in the chosen encoding.
+///
+/// The last `max` events are kept, and a new client gets those first. A client can send a
+/// [`Hello`](../handshake/struct.Hello.html) right after connecting, to pick the encoding and
+/// events, and to only get the events after a sequence id. Each event then has the sequence id
+/// from the daemon in its `seq` field. If some of the events after it were already evicted, the
+/// client gets an [`Event::Gap`](../event/enum.Event.html#variant.Gap) first.
+///
+/// Clients that send nothing get version 1, what streamchatd sent before the handshake: only the
+/// chat messages, as bare [`Message`](../struct.Message.html)s
+#[derive(Debug)]
+pub struct Socket {
+    tx: channel::Sender<(Option<u64>, Event)>,
+    rx: channel::Receiver<(Option<u64>, Event)>,
+    // the id of the newest event dropped before the run loop got it
+    dropped: Arc<AtomicU64>,
+}
+
+impl Socket {
+    /// Listens on `addr`, keeping a backlog of `max` events
+    pub fn start(addr: &str, max: usize, encoding: Encoding) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        log::debug!(
+            "socket transport listening on: {} ({})",
+            listener.local_addr()?,
+            encoding
+        );
+        Ok(Self::run_loop(listener, max, encoding))
+    }
+
+    /// Listens on the Unix socket at `path`, keeping a backlog of `max` events.
+    ///
+    /// A stale socket file left at `path` is replaced. The socket is made in a private directory
+    /// next to `path` and moved there once the `options` are applied, so nobody can connect to it
+    /// before that
+    #[cfg(unix)]
+    pub fn start_unix(
+        path: impl AsRef<Path>,
+        options: &UnixOptions,
+        max: usize,
+        encoding: Encoding,
+    ) -> io::Result<Self> {
+        use std::os::unix::fs::{DirBuilderExt as _, FileTypeExt as _, PermissionsExt as _};
+
+        let path = path.as_ref();
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if metadata.file_type().is_socket() {
+                log::debug!("removing the old socket at: {}", path.display());
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        // the socket is bound in a directory only we can get into, and moved into place once it
+        // has its permissions. otherwise anyone could connect before they are set
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let dir = parent.join(format!(".streamchat-{}", std::process::id()));
+        let temp = dir.join("socket");
+        // left over from an earlier process with the same id
+        let _ = std::fs::remove_file(&temp);
+        let _ = std::fs::remove_dir(&dir);
+        std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+        let bind = || -> io::Result<UnixListener> {
+            let listener = UnixListener::bind(&temp)?;
+            if let Some(mode) = options.mode {
+                std::fs::set_permissions(&temp, std::fs::Permissions::from_mode(mode))?;
+            }
+            if options.uid.is_some() || options.gid.is_some() {
+                std::os::unix::fs::chown(&temp, options.uid, options.gid)?;
+            }
+            std::fs::rename(&temp, path)?;
+            Ok(listener)
+        };
+        let listener = bind();
+        let _ = std::fs::remove_file(&temp);
+        let _ = std::fs::remove_dir(&dir);
+
+        let listener = listener?;
+        listener.set_nonblocking(true)?;
+
+        log::debug!(
+            "socket transport listening on: {} ({})",
+            path.display(),
+            encoding
+        );
+        Ok(Self::run_loop(listener, max, encoding))
+    }
+
+    fn run_loop<L: Listener>(listener: L, size: usize, encoding: Encoding) -> Self {
+        struct Client<S> {
+            id: u8,
+            // the sequence id of the last event this client was sent
+            last: u64,
+            stream: S,
+            // version 1 clients only get messages, without their sequence id
+            version: Version,
+            encoding: Encoding,
+            hello: Hello,
+        }
+
+        let (tx, rx) = channel::bounded(size);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let this = Self {
+            tx,
+            rx: rx.clone(),
+            dropped: Arc::clone(&dropped),
+        };
+
+        thread::spawn(move || {
+            let mut queue = Queue::new(size);
+            let (mut clients, mut alive) = (vec![], vec![]);
+            let (mut pending, mut waiting) = (vec![], vec![]);
+            let mut next_id = 0_u8;
+
+            log::debug!("starting run loop");
+            loop {
+                'accept: loop {
+                    match listener.accept() {
+                        Ok((stream, addr)) => {
+                            log::info!("accepted client from: {}", addr);
+                            match Pending::new(next_id, stream) {
+                                Ok(client) => pending.push(client),
+                                Err(err) => log::warn!("cannot set up client: {}", err),
+                            }
+                            next_id = next_id.wrapping_add(1);
+                            break 'accept;
+                        }
+                        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(err) => log::warn!("error accepting client: {}", err),
+                    }
+
+                    if let Ok((seq, event)) = rx.try_recv() {
+                        // events without an id from the daemon are numbered here
+                        let seq = seq.unwrap_or_default().max(queue.last_seq() + 1);
+                        queue.push_with_seq(seq, Entry::new(seq, event, encoding));
+                        break 'accept;
+                    }
+
+                    if !pending.is_empty() {
+                        thread::park_timeout(PENDING_POLL);
+                        break 'accept;
+                    }
+
+                    // TODO: this should probably still be a park and not a yield
+                    // TODO: look into crossbeams backup and/or parker
+                    thread::park_timeout(std::time::Duration::from_millis(100))
+                }
+
+                for mut client in pending.drain(..) {
+                    let request = match client.poll() {
+                        Ok(Some(request)) => request,
+                        Ok(None) => {
+                            waiting.push(client);
+                            continue;
+                        }
+                        Err(err) => {
+                            log::debug!("client {} went away: {}", client.id, err);
+                            client.stream.close();
+                            continue;
+                        }
+                    };
+
+                    let Pending { id, mut stream, .. } = client;
+                    let (version, hello, welcome) = match request {
+                        Request::Silent => (Version(1), Hello::default(), None),
+                        Request::Hello(mut hello) => match negotiate(&mut hello, encoding) {
+                            Ok(welcome) => (Version::CURRENT, hello, welcome),
+                            Err(reason) => {
+                                reject(id, stream, hello.encoding.unwrap_or(encoding), reason);
+                                continue;
+                            }
+                        },
+                        Request::Invalid(reason) => {
+                            reject(id, stream, encoding, reason);
+                            continue;
+                        }
+                    };
+
+                    let client_encoding = hello.encoding.unwrap_or(encoding);
+                    let mut frames = vec![];
+                    if let Some(welcome) = welcome {
+                        frames.push(client_encoding.encode_frame(&Reply::Welcome(welcome)));
+                    }
+                    let last = match hello.resume {
+                        Some(after) => {
+                            let evicted = queue.evicted_seq().max(dropped.load(Ordering::SeqCst));
+                            let (last, gap) = resume_point(&queue, evicted, after);
+                            if let Some(gap) = gap {
+                                log::debug!("client {} missed the events after {}", id, after);
+                                frames.push(client_encoding.encode(&Event::Gap(gap)));
+                            }
+                            last
+                        }
+                        None => 0,
+                    };
+                    if let Err(err) = frames.iter().try_for_each(|frame| stream.write_all(frame)) {
+                        log::debug!("client {} went away: {}", id, err);
+                        stream.close();
+                        continue;
+                    }
+
+                    clients.push(Client {
+                        id,
+                        last,
+                        stream,
+                        version,
+                        encoding: client_encoding,
+                        hello,
+                    });
+                }
+                std::mem::swap(&mut pending, &mut waiting);
+
+                'drain: for client in clients.drain(..) {
+                    let mut client = client;
+
+                    // if we cannot write to client, mark it as dead
+                    // (e.g. skip adding it to the alive list)
+                    macro_rules! try_client {
+                        ($f:expr) => {
+                            if let Err(_err) = $f() {
+                                log::debug!("client appears to be disconnected: {}", client.id);
+                                client.stream.close();
+                                continue 'drain;
+                            }
+                        };
+                    }
+
+                    for (seq, entry) in queue.since(client.last) {
+                        if client.version < Version::CURRENT {
+                            if let Some(frame) = &entry.v1 {
+                                try_client!(|| client.stream.write_all(frame));
+                            }
+                            continue;
+                        }
+
+                        let Hello {
+                            channels,
+                            events,
+                            filter,
+                            ..
+                        } = &client.hello;
+                        if !handshake::is_wanted(channels, events, filter.as_ref(), &entry.event) {
+                            continue;
+                        }
+                        if client.encoding == encoding {
+                            try_client!(|| client.stream.write_all(&entry.frame));
+                        } else {
+                            let frame = client.encoding.encode_seq(seq, &entry.event);
+                            try_client!(|| client.stream.write_all(&frame));
+                        }
+                    }
+
+                    try_client!(|| client.stream.flush());
+
+                    client.last = queue.last_seq();
+                    alive.push(client)
+                }
+
+                log::trace!("new client list count: {}", alive.len());
+                std::mem::swap(&mut clients, &mut alive);
+                clients.shrink_to_fit();
+            }
+        });
+        this
+    }
+}
+
+// an event in the backlog, encoded for the clients using the socket's encoding
+struct Entry {
+    event: Event,
+    frame: Vec<u8>,
+    // messages, as version 1 clients get them
+    v1: Option<Vec<u8>>,
+}
+
+impl Entry {
+    fn new(seq: u64, event: Event, encoding: Encoding) -> Self {
+        let v1 = match &event {
+            Event::Message(msg) => Some(encoding.encode_v1(msg)),
+            _ => None,
+        };
+        Self {
+            frame: encoding.encode_seq(seq, &event),
+            event,
+            v1,
+        }
+    }
+}
+
+// what a new client asked for
+enum Request {
+    // nothing, so it gets version 1
+    Silent,
+    Hello(Hello),
+    // the hello couldn't be read
+    Invalid(String),
+}
+
+// checks what the client asked for, dropping the events that don't exist. the answer to send,
+// if the hello has versions, or why the client is rejected
+fn negotiate(hello: &mut Hello, encoding: Encoding) -> Result<Option<Welcome>, String> {
+    for channel in &mut hello.channels {
+        *channel = crate::normalize_channel(channel);
+    }
+
+    let asked = !hello.events.is_empty();
+    hello
+        .events
+        .retain(|name| Event::NAMES.contains(&name.as_str()));
+    if asked && hello.events.is_empty() {
+        return Err("none of the events are known".to_string());
+    }
+
+    if hello.versions.is_empty() {
+        return Ok(None);
+    }
+    if !hello.versions.contains(&Version::CURRENT) {
+        let Version(current) = Version::CURRENT;
+        return Err(format!("only version {} is supported", current));
+    }
+
+    Ok(Some(Welcome {
+        version: Version::CURRENT,
+        encoding: hello.encoding.unwrap_or(encoding),
+        channels: hello.channels.clone(),
+        events: hello.events.clone(),
+        filter: hello.filter.clone(),
+        resume: hello.resume,
+    }))
+}
+
+fn reject<S: Stream>(id: u8, mut stream: S, encoding: Encoding, reason: String) {
+    log::warn!("rejecting client {}: {}", id, reason);
+    let _ = stream.write_all(&encoding.encode_frame(&Reply::Rejected(reason)));
+    stream.close();
+}
+
+/// A client that can still send a hello
+struct Pending<S> {
+    id: u8,
+    stream: S,
+    buf: Vec<u8>,
+    accepted: Instant,
+}
+
+impl<S: Stream> Pending<S> {
+    fn new(id: u8, stream: S) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            id,
+            stream,
+            buf: vec![],
+            accepted: Instant::now(),
+        })
+    }
+
+    // Some once the client sent a hello, or is done waiting. None if it could still send one
+    fn poll(&mut self) -> io::Result<Option<Request>> {
+        let mut buf = [0; 256];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buf.extend_from_slice(&buf[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+            if self.buf.len() > MAX_HELLO {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "hello too long"));
+            }
+        }
+
+        let request = match self.buf.iter().position(|&b| b == b'\n') {
+            Some(pos) => match serde_json::from_slice::<Hello>(&self.buf[..pos]) {
+                Ok(hello) => Request::Hello(hello),
+                Err(err) => Request::Invalid(format!("invalid hello: {}", err)),
+            },
+            None if self.accepted.elapsed() < HELLO_WAIT => return Ok(None),
+            None => Request::Silent,
+        };
+
+        self.stream.set_nonblocking(false)?;
+        Ok(Some(request))
+    }
+}
+
+/// Who can connect to a Unix socket
+#[cfg(unix)]
+#[derive(Debug, Default, Clone)]
+pub struct UnixOptions {
+    /// The permissions of the socket file, e.g. `0o660`
+    pub mode: Option<u32>,
+    /// The user id to own the socket file
+    pub uid: Option<u32>,
+    /// The group id to own the socket file
+    pub gid: Option<u32>,
+}
+
+// what the run loop accepts clients from
+trait Listener: Send + 'static {
+    type Stream: Stream;
+    // returns the stream and a description of where it is from
+    fn accept(&self) -> io::Result<(Self::Stream, String)>;
+}
+
+trait Stream: Read + Write + Send + 'static {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+    fn close(&self);
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+    fn accept(&self) -> io::Result<(Self::Stream, String)> {
+        TcpListener::accept(self).map(|(stream, addr)| (stream, addr.to_string()))
+    }
+}
+
+impl Stream for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+    fn accept(&self) -> io::Result<(Self::Stream, String)> {
+        UnixListener::accept(self).map(|(stream, addr)| (stream, format!("{:?}", addr)))
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+impl Transport for Socket {
+    fn send(&mut self, data: Message) -> Result<(), Box<dyn std::error::Error>> {
+        self.send_event(data.into())
+    }
+
+    fn send_event(&mut self, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        self.queue(None, data)
+    }
+
+    fn send_sequenced(&mut self, seq: u64, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        self.queue(Some(seq), data)
+    }
+}
+
+impl Socket {
+    fn queue(&mut self, seq: Option<u64>, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        use std::io::{Error, ErrorKind};
+
+        if self.rx.is_full() {
+            log::trace!("buffer full, dropping one");
+            let (seq, _) = self
+                .rx
+                .recv()
+                .map_err(|e| Box::new(Error::new(ErrorKind::NotConnected, e)))
+                .map_err(|e| e as Box<dyn std::error::Error>)?;
+            if let Some(seq) = seq {
+                self.dropped.fetch_max(seq, Ordering::SeqCst);
+            }
+        }
+
+        self.tx
+            .send((seq, data))
+            .map_err(|e| Box::new(Error::new(ErrorKind::Interrupted, e)))
+            .map_err(|e| e as Box<dyn std::error::Error>)
+    }
+}
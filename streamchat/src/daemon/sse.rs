@@ -0,0 +1,225 @@
+use std::io::{self, prelude::*, BufReader};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::resume_point;
+use crate::encoding::Sequenced;
+use crate::{Event, Message, Queue, Transport};
+use crossbeam_channel as channel;
+
+// a comment is sent this often, so dead clients are noticed even when chat is quiet
+const KEEPALIVE: Duration = Duration::from_secs(15);
+// a client that can't take a write within this is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// how long a client has to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+// how long browsers should wait before reconnecting, in milliseconds
+const RETRY: u64 = 3000;
+// how many frames a client can fall behind, on top of the backlog, before it is dropped
+const CLIENT_BUFFER: usize = 64;
+
+/// Serves the events as [Server-Sent Events](https://html.spec.whatwg.org/multipage/server-sent-events.html)
+/// on `GET /events`.
+///
+/// Each event has its sequence id as the `id:` and the JSON as the `data:`. Browsers send the
+/// last id they saw as `Last-Event-ID` when they reconnect, and get what they missed from the
+/// last `max` events. Without it, a new client gets all of those first. A client that falls too
+/// far behind is dropped, so one stalled browser doesn't hold up the others
+pub struct EventStream {
+    shared: Arc<Mutex<Shared>>,
+}
+
+struct Shared {
+    queue: Queue<Frame>,
+    // each client has a thread writing what is sent here, so a slow one doesn't hold up the rest
+    clients: Vec<channel::Sender<Frame>>,
+}
+
+type Frame = Arc<str>;
+
+impl EventStream {
+    /// Listens on `addr`, keeping a backlog of `max` events
+    pub fn start(addr: &str, max: usize) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        log::debug!(
+            "event stream transport listening on: {}",
+            listener.local_addr()?
+        );
+
+        let shared = Arc::new(Mutex::new(Shared {
+            queue: Queue::new(max),
+            clients: vec![],
+        }));
+
+        let accept = Arc::clone(&shared);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("error accepting client: {}", err);
+                        continue;
+                    }
+                };
+                // reading the request can block, so it doesn't hold up the other clients
+                let shared = Arc::clone(&accept);
+                thread::spawn(move || {
+                    if let Err(err) = accept_client(stream, &shared) {
+                        log::debug!("cannot accept event stream client: {}", err);
+                    }
+                });
+            }
+        });
+
+        let keepalive = Arc::clone(&shared);
+        thread::spawn(move || loop {
+            thread::sleep(KEEPALIVE);
+            keepalive
+                .lock()
+                .expect("event stream state")
+                .broadcast(&Frame::from(":\n\n"));
+        });
+
+        Ok(Self { shared })
+    }
+}
+
+impl Shared {
+    // queues `frame` for every client, dropping the ones that went away or fell too far behind
+    fn broadcast(&mut self, frame: &Frame) {
+        self.clients
+            .retain(|client| match client.try_send(Arc::clone(frame)) {
+                Ok(..) => true,
+                Err(channel::TrySendError::Full(..)) => {
+                    log::debug!("event stream client fell behind, dropping it");
+                    false
+                }
+                Err(channel::TrySendError::Disconnected(..)) => false,
+            });
+    }
+}
+
+impl std::fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventStream").finish()
+    }
+}
+
+impl Transport for EventStream {
+    fn send(&mut self, data: Message) -> Result<(), Box<dyn std::error::Error>> {
+        self.send_event(data.into())
+    }
+
+    fn send_event(&mut self, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        self.push(None, data)
+    }
+
+    fn send_sequenced(&mut self, seq: u64, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        self.push(Some(seq), data)
+    }
+}
+
+impl EventStream {
+    fn push(&mut self, seq: Option<u64>, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        let mut shared = self.shared.lock().expect("event stream state");
+        let seq = seq.unwrap_or_default().max(shared.queue.last_seq() + 1);
+        let json = serde_json::to_string(&Sequenced { seq, event: &data })?;
+        let frame = Frame::from(format!("id: {}\ndata: {}\n\n", seq, json));
+        shared.broadcast(&frame);
+        shared.queue.push_with_seq(seq, frame);
+        Ok(())
+    }
+}
+
+fn accept_client(mut stream: TcpStream, shared: &Mutex<Shared>) -> io::Result<()> {
+    let addr = stream.peer_addr()?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next(), parts.next());
+
+    let (mut last, mut line) = (None, String::new());
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let mut header = line.splitn(2, ':');
+        if let (Some(key), Some(value)) = (header.next(), header.next()) {
+            if key.trim().eq_ignore_ascii_case("last-event-id") {
+                last = value.trim().parse().ok();
+            }
+        }
+    }
+
+    match (method, path.and_then(|path| path.split('?').next())) {
+        (Some("GET"), Some("/events")) => {}
+        (Some(_), Some("/events")) => return respond(stream, "405 Method Not Allowed"),
+        _ => return respond(stream, "404 Not Found"),
+    }
+
+    log::info!("accepted event stream client from: {}", addr);
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/event-stream\r\n\
+         Cache-Control: no-cache\r\n\
+         Connection: keep-alive\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         \r\n\
+         retry: {}\n\n",
+        RETRY
+    )?;
+
+    stream.flush()?;
+
+    // the backlog is queued while holding the lock, so a client gets every event once
+    let mut shared = shared.lock().expect("event stream state");
+    let (tx, rx) = channel::bounded(shared.queue.size() + CLIENT_BUFFER);
+    let last = match last {
+        Some(after) => {
+            let (last, gap) = resume_point(&shared.queue, shared.queue.evicted_seq(), after);
+            if let Some(gap) = gap {
+                let json = serde_json::to_string(&Event::Gap(gap))?;
+                let _ = tx.try_send(Frame::from(format!("data: {}\n\n", json)));
+            }
+            last
+        }
+        None => 0,
+    };
+    for (_, frame) in shared.queue.since(last) {
+        let _ = tx.try_send(Arc::clone(frame));
+    }
+    shared.clients.push(tx);
+    log::trace!("new event stream client count: {}", shared.clients.len());
+    drop(shared);
+
+    thread::spawn(move || {
+        for frame in rx {
+            if let Err(err) = stream
+                .write_all(frame.as_bytes())
+                .and_then(|_| stream.flush())
+            {
+                log::debug!("event stream client appears to be disconnected: {}", err);
+                break;
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, status: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    )?;
+    stream.flush()
+}
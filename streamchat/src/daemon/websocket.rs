@@ -0,0 +1,179 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::encoding::Sequenced;
+use crate::{Event, Message, Queue, Transport};
+use ws::util::Token;
+
+// how often clients are pinged. a client that hasn't answered the last ping by the next one is
+// dropped
+const PING_INTERVAL: u64 = 30 * 1000;
+const PING: Token = Token(1);
+
+/// Sends the events to every client connected over a WebSocket, as JSON text frames.
+///
+/// Like [`Socket`](./struct.Socket.html), the last `max` events are kept and a new client gets
+/// those first. This lets browser overlays connect directly
+pub struct WebSocket {
+    shared: Arc<Mutex<Shared>>,
+}
+
+struct Shared {
+    queue: Queue<String>,
+    // the clients that have been sent the backlog, by connection id
+    clients: Vec<(u32, ws::Sender)>,
+}
+
+impl WebSocket {
+    /// Listens on `addr`, keeping a backlog of `max` events
+    pub fn start(addr: &str, max: usize) -> io::Result<Self> {
+        let shared = Arc::new(Mutex::new(Shared {
+            queue: Queue::new(max),
+            clients: vec![],
+        }));
+
+        let factory = {
+            let shared = Arc::clone(&shared);
+            move |out: ws::Sender| Client {
+                out,
+                shared: Arc::clone(&shared),
+                waiting: false,
+            }
+        };
+
+        let socket = ws::WebSocket::new(factory).map_err(into_io)?;
+        let socket = socket.bind(addr).map_err(into_io)?;
+        log::debug!("websocket transport listening on: {}", socket.local_addr()?);
+        thread::spawn(move || {
+            if let Err(err) = socket.run() {
+                log::error!("websocket transport stopped: {}", err);
+            }
+        });
+
+        Ok(Self { shared })
+    }
+}
+
+impl std::fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebSocket").finish()
+    }
+}
+
+impl Transport for WebSocket {
+    fn send(&mut self, data: Message) -> Result<(), Box<dyn std::error::Error>> {
+        self.send_event(data.into())
+    }
+
+    fn send_event(&mut self, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        self.push(None, data)
+    }
+
+    fn send_sequenced(&mut self, seq: u64, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        self.push(Some(seq), data)
+    }
+}
+
+impl WebSocket {
+    fn push(&mut self, seq: Option<u64>, data: Event) -> Result<(), Box<dyn std::error::Error>> {
+        let mut shared = self.shared.lock().expect("websocket state");
+        let seq = seq.unwrap_or_default().max(shared.queue.last_seq() + 1);
+        let json = serde_json::to_string(&Sequenced { seq, event: &data })?;
+
+        // the backlog is replayed while holding the lock, so a client gets every event once
+        shared.queue.push_with_seq(seq, json.clone());
+        shared
+            .clients
+            .retain(|(id, out)| match out.send(json.as_str()) {
+                Ok(..) => true,
+                Err(err) => {
+                    log::debug!(
+                        "websocket client appears to be disconnected: {}: {}",
+                        id,
+                        err
+                    );
+                    false
+                }
+            });
+        Ok(())
+    }
+}
+
+struct Client {
+    out: ws::Sender,
+    shared: Arc<Mutex<Shared>>,
+    // whether a ping hasn't been answered yet
+    waiting: bool,
+}
+
+impl Client {
+    fn remove(&self) {
+        let id = self.out.connection_id();
+        let mut shared = self.shared.lock().expect("websocket state");
+        shared.clients.retain(|(client, _)| *client != id);
+        log::trace!("new websocket client count: {}", shared.clients.len());
+    }
+}
+
+impl ws::Handler for Client {
+    fn on_open(&mut self, shake: ws::Handshake) -> ws::Result<()> {
+        match shake.remote_addr() {
+            Ok(Some(addr)) => log::info!("accepted websocket client from: {}", addr),
+            _ => log::info!("accepted websocket client"),
+        }
+
+        let mut shared = self.shared.lock().expect("websocket state");
+        for json in shared.queue.iter() {
+            self.out.send(json.as_str())?;
+        }
+        shared
+            .clients
+            .push((self.out.connection_id(), self.out.clone()));
+
+        self.out.timeout(PING_INTERVAL, PING)
+    }
+
+    // clients aren't expected to send anything
+    fn on_message(&mut self, _msg: ws::Message) -> ws::Result<()> {
+        Ok(())
+    }
+
+    fn on_frame(&mut self, frame: ws::Frame) -> ws::Result<Option<ws::Frame>> {
+        if frame.opcode() == ws::OpCode::Pong {
+            self.waiting = false;
+        }
+        Ok(Some(frame))
+    }
+
+    fn on_timeout(&mut self, event: Token) -> ws::Result<()> {
+        if event != PING {
+            return Ok(());
+        }
+        if self.waiting {
+            log::debug!("websocket client didn't answer a ping, dropping it");
+            self.remove();
+            return self.out.close(ws::CloseCode::Away);
+        }
+        self.waiting = true;
+        self.out.ping(vec![])?;
+        self.out.timeout(PING_INTERVAL, PING)
+    }
+
+    fn on_close(&mut self, code: ws::CloseCode, reason: &str) {
+        log::debug!("websocket client closed: {:?} {}", code, reason);
+        self.remove();
+    }
+
+    fn on_error(&mut self, err: ws::Error) {
+        log::warn!("websocket client error: {}", err);
+        self.remove();
+    }
+}
+
+fn into_io(err: ws::Error) -> io::Error {
+    match err.kind {
+        ws::ErrorKind::Io(err) => err,
+        _ => io::Error::new(io::ErrorKind::Other, err.to_string()),
+    }
+}
@@ -0,0 +1,310 @@
+//! How events are written on the wire
+//!
+//! [`Json`](./enum.Encoding.html#variant.Json) is one JSON object per line. It is the default,
+//! and what older clients expect.
+//!
+//! [`MessagePack`](./enum.Encoding.html#variant.MessagePack) frames are a big-endian `u32`
+//! length followed by that many bytes of [MessagePack](https://msgpack.org). The fields are
+//! named, so a frame decodes to the same structure as the JSON.
+//!
+//! Events from the daemon have a `seq` field next to the `event` field, with the sequence id
+//! the daemon gave them. See [`seq`](./enum.Encoding.html#method.seq)
+//!
+//! Clients can ask the socket for either encoding in their
+//! [hello](../handshake/struct.Hello.html#structfield.encoding)
+use crate::client::Error;
+#[cfg(feature = "daemon")]
+use crate::message::V1;
+use crate::{Event, Message, Version};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Frames larger than this are treated as a broken stream
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// An event with its sequence id, as the daemon sends it
+#[derive(Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "schema", schemars(rename = "Event"))]
+pub(crate) struct Sequenced<'a> {
+    /// The id the daemon gave the event. They only go up, so a client can resume after the last
+    /// one it saw
+    pub(crate) seq: u64,
+    #[serde(flatten)]
+    pub(crate) event: &'a Event,
+}
+
+// a frame as it is read, in one pass. bare messages, from older daemons or sent to clients
+// without a hello, have no `seq` or `event` field
+#[derive(Deserialize)]
+struct Frame {
+    seq: Option<u64>,
+    #[serde(flatten)]
+    event: Payload,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Payload {
+    Event(Event),
+    Message(Message),
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// Newline separated JSON
+    #[default]
+    Json,
+    /// Length prefixed MessagePack
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+impl Encoding {
+    /// Encodes `event` as a complete frame, including the newline or the length
+    pub fn encode(self, event: &Event) -> Vec<u8> {
+        self.encode_frame(event)
+    }
+
+    /// Like [`encode`](#method.encode), with a `seq` field added to the event
+    pub fn encode_seq(self, seq: u64, event: &Event) -> Vec<u8> {
+        self.encode_frame(&Sequenced { seq, event })
+    }
+
+    // a message as version 1 sent it, for clients that didn't send a hello
+    #[cfg(feature = "daemon")]
+    pub(crate) fn encode_v1(self, msg: &Message) -> Vec<u8> {
+        self.encode_frame(&V1::from(msg))
+    }
+
+    /// The `seq` field of a frame, if it has one
+    pub fn seq(self, frame: &[u8]) -> Option<u64> {
+        #[derive(Deserialize)]
+        struct Probe {
+            seq: Option<u64>,
+        }
+        self.decode_frame::<Probe>(frame)
+            .and_then(|probe| probe.seq)
+    }
+
+    // a frame as anything other than an event, e.g. a handshake reply
+    pub(crate) fn decode_frame<T: DeserializeOwned>(self, frame: &[u8]) -> Option<T> {
+        match self {
+            Encoding::Json => serde_json::from_slice(frame).ok(),
+            Encoding::MessagePack => rmp_serde::from_slice(frame).ok(),
+        }
+    }
+
+    pub(crate) fn encode_frame(self, item: &impl Serialize) -> Vec<u8> {
+        match self {
+            Encoding::Json => {
+                let mut data = serde_json::to_vec(item).expect("valid json");
+                data.push(b'\n');
+                data
+            }
+            Encoding::MessagePack => {
+                let data = rmp_serde::to_vec_named(item).expect("valid msgpack");
+                let mut frame = Vec::with_capacity(data.len() + 4);
+                frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                frame.extend(data);
+                frame
+            }
+        }
+    }
+
+    /// Decodes a single frame, as returned by [`next_frame`](#method.next_frame)
+    pub fn decode(self, frame: &[u8]) -> Result<Event, Error> {
+        self.decode_seq(frame).map(|(_, event)| event)
+    }
+
+    /// Like [`decode`](#method.decode), also returning the [`seq`](#method.seq) of the frame
+    pub fn decode_seq(self, frame: &[u8]) -> Result<(Option<u64>, Event), Error> {
+        let result = match self {
+            Encoding::Json => {
+                serde_json::from_slice::<Frame>(frame).map_err(|err| Error::Malformed {
+                    line: String::from_utf8_lossy(frame).trim_end().to_string(),
+                    error: serde_json::from_slice::<Event>(frame).err().unwrap_or(err),
+                })
+            }
+            Encoding::MessagePack => rmp_serde::from_slice::<Frame>(frame).map_err(|err| {
+                Error::Undecodable(rmp_serde::from_slice::<Event>(frame).err().unwrap_or(err))
+            }),
+        };
+
+        let (seq, event) = match result {
+            Ok(Frame {
+                seq,
+                event: Payload::Event(event),
+            }) => (seq, event),
+            Ok(Frame {
+                seq,
+                event: Payload::Message(msg),
+            }) => (seq, Event::Message(msg)),
+            // a newer message could fail to decode for reasons other than the version
+            Err(err) => return Err(self.newer_version(frame).unwrap_or(err)),
+        };
+
+        match &event {
+            Event::Message(msg) if msg.version > Version::CURRENT => {
+                Err(Error::UnsupportedVersion(msg.version))
+            }
+            _ => Ok((seq, event)),
+        }
+    }
+
+    fn newer_version(self, frame: &[u8]) -> Option<Error> {
+        #[derive(Deserialize)]
+        struct Probe {
+            version: Option<Version>,
+        }
+        match self.decode_frame::<Probe>(frame)?.version? {
+            version if version > Version::CURRENT => Some(Error::UnsupportedVersion(version)),
+            _ => None,
+        }
+    }
+
+    /// Takes the first complete frame out of `buf`, without its newline or length.
+    /// Blank JSON lines are skipped
+    pub fn next_frame(self, buf: &mut Vec<u8>) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            Encoding::Json => loop {
+                let pos = match buf.iter().position(|&b| b == b'\n') {
+                    Some(pos) => pos,
+                    None if buf.len() > MAX_FRAME_SIZE => return Err(too_large(buf.len())),
+                    None => return Ok(None),
+                };
+                let mut line = buf.drain(..=pos).collect::<Vec<_>>();
+                line.pop();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return Ok(Some(line));
+            },
+            Encoding::MessagePack => {
+                if buf.len() < 4 {
+                    return Ok(None);
+                }
+                let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+                if len > MAX_FRAME_SIZE {
+                    return Err(too_large(len));
+                }
+                if buf.len() < len + 4 {
+                    return Ok(None);
+                }
+                let frame = buf.drain(..len + 4).skip(4).collect();
+                Ok(Some(frame))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "json" => Ok(Encoding::Json),
+            "msgpack" | "messagepack" => Ok(Encoding::MessagePack),
+            _ => Err(format!(
+                "unknown encoding `{}`, expected json or msgpack",
+                input
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Encoding::Json => write!(f, "json"),
+            Encoding::MessagePack => write!(f, "msgpack"),
+        }
+    }
+}
+
+// the stream can't be resynced after this
+fn too_large(len: usize) -> Error {
+    let err = format!("frame of {} bytes is over the limit", len);
+    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Gap;
+
+    fn gap(after: u64) -> Event {
+        Event::Gap(Gap {
+            after,
+            next: after + 1,
+        })
+    }
+
+    fn after(event: &Event) -> u64 {
+        match event {
+            Event::Gap(gap) => gap.after,
+            event => panic!("expected a gap, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn json_frames_are_lines() {
+        let mut buf = b"\n  \n{\"event\":\"gap\",\"after\":1,\"next\":2}\n{\"ev".to_vec();
+        let frame = Encoding::Json.next_frame(&mut buf).unwrap().unwrap();
+        assert_eq!(frame, br#"{"event":"gap","after":1,"next":2}"#.to_vec());
+        assert_eq!(buf, b"{\"ev".to_vec());
+        assert_eq!(Encoding::Json.next_frame(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn msgpack_frames_are_length_prefixed() {
+        let mut buf = Encoding::MessagePack.encode_seq(7, &gap(1));
+        buf.extend(Encoding::MessagePack.encode(&gap(2)));
+        let partial = buf.split_off(buf.len() - 3);
+
+        let frame = Encoding::MessagePack.next_frame(&mut buf).unwrap().unwrap();
+        let (seq, event) = Encoding::MessagePack.decode_seq(&frame).unwrap();
+        assert_eq!((seq, after(&event)), (Some(7), 1));
+
+        // the second frame is missing its last bytes
+        assert_eq!(Encoding::MessagePack.next_frame(&mut buf).unwrap(), None);
+        buf.extend(partial);
+        let frame = Encoding::MessagePack.next_frame(&mut buf).unwrap().unwrap();
+        let (seq, event) = Encoding::MessagePack.decode_seq(&frame).unwrap();
+        assert_eq!((seq, after(&event)), (None, 2));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn oversized_frames_are_errors() {
+        let mut buf = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes().to_vec();
+        assert!(Encoding::MessagePack.next_frame(&mut buf).is_err());
+
+        let mut buf = vec![b'a'; MAX_FRAME_SIZE + 1];
+        assert!(Encoding::Json.next_frame(&mut buf).is_err());
+    }
+
+    #[test]
+    fn decode_reads_the_seq() {
+        for &encoding in &[Encoding::Json, Encoding::MessagePack] {
+            let mut buf = encoding.encode_seq(42, &gap(3));
+            let frame = encoding.next_frame(&mut buf).unwrap().unwrap();
+            assert_eq!(encoding.seq(&frame), Some(42));
+            let (seq, event) = encoding.decode_seq(&frame).unwrap();
+            assert_eq!((seq, after(&event)), (Some(42), 3));
+        }
+    }
+
+    #[test]
+    fn newer_versions_are_reported() {
+        let frame = br#"{"version":9,"userid":"1"}"#;
+        match Encoding::Json.decode(frame) {
+            Err(Error::UnsupportedVersion(Version(9))) => {}
+            result => panic!("expected an unsupported version, got {:?}", result),
+        }
+        match Encoding::Json.decode(br#"{"event":"gap","after":"x"}"#) {
+            Err(Error::Malformed { .. }) => {}
+            result => panic!("expected a malformed line, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use crate::{Message, Tags};
+use serde::{Deserialize, Serialize};
+
+/// Everything that can happen in a chat
+///
+/// On the wire this is the same json object as the inner type, with an added `event` field
+/// naming the variant (e.g. `"event": "message"`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A chat message
+    Message(Message),
+    /// A sub, resub or gifted sub (`USERNOTICE`)
+    Subscription(Subscription),
+    /// An incoming raid (`USERNOTICE`)
+    Raid(Raid),
+    /// A user was timed out (`CLEARCHAT`)
+    Timeout(Timeout),
+    /// A user was banned (`CLEARCHAT`)
+    Ban(Ban),
+    /// The whole chat was cleared (`CLEARCHAT`)
+    Clear(Clear),
+    /// A single message was deleted (`CLEARMSG`)
+    Delete(Delete),
+    /// The room settings changed (`ROOMSTATE`)
+    RoomState(RoomState),
+    /// A user joined the channel
+    Join(Membership),
+    /// A user left the channel
+    Part(Membership),
+    /// A private message to us
+    Whisper(Whisper),
+    /// Sent by the daemon instead of events that can't be resumed anymore
+    Gap(Gap),
+}
+
+impl Event {
+    /// Converts a message from Twitch, returning `None` for things that aren't chat events
+    #[cfg(feature = "twitch")]
+    pub fn from_twitch(msg: crate::twitch::Message) -> Option<Self> {
+        use crate::twitch;
+        use std::convert::TryFrom;
+
+        use twitch::Message as T;
+        let event = match msg {
+            T::PrivMsg(msg) => match Message::try_from(msg) {
+                Ok(msg) => Event::Message(msg),
+                Err(err) => {
+                    log::warn!("skipping message: {}", err);
+                    return None;
+                }
+            },
+            T::UserNotice(msg) => user_notice(&msg)?,
+            T::ClearChat(msg) => clear_chat(&msg),
+            T::ClearMsg(msg) => Event::Delete(Delete {
+                channel: channel(msg.channel()),
+                name: msg.tags().get("login").unwrap_or_default().to_string(),
+                id: msg.tags().get("target-msg-id")?.to_string(),
+                data: msg.message().to_string(),
+            }),
+            T::RoomState(msg) => Event::RoomState(RoomState::from_tags(
+                channel(msg.channel()),
+                &msg.tags().into(),
+            )),
+            T::Join(msg) => Event::Join(Membership {
+                channel: channel(msg.channel()),
+                name: msg.user().to_string(),
+            }),
+            T::Part(msg) => Event::Part(Membership {
+                channel: channel(msg.channel()),
+                name: msg.user().to_string(),
+            }),
+            T::Irc(msg) => whisper(*msg)?,
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    /// The names used in the `event` field, one for each variant
+    pub const NAMES: &'static [&'static str] = &[
+        "message",
+        "subscription",
+        "raid",
+        "timeout",
+        "ban",
+        "clear",
+        "delete",
+        "room_state",
+        "join",
+        "part",
+        "whisper",
+        "gap",
+    ];
+
+    /// The name in the `event` field, e.g. `"room_state"`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Message(..) => "message",
+            Event::Subscription(..) => "subscription",
+            Event::Raid(..) => "raid",
+            Event::Timeout(..) => "timeout",
+            Event::Ban(..) => "ban",
+            Event::Clear(..) => "clear",
+            Event::Delete(..) => "delete",
+            Event::RoomState(..) => "room_state",
+            Event::Join(..) => "join",
+            Event::Part(..) => "part",
+            Event::Whisper(..) => "whisper",
+            Event::Gap(..) => "gap",
+        }
+    }
+
+    /// The channel this event happened in, whispers and gaps don't have one
+    pub fn channel(&self) -> Option<&str> {
+        let channel = match self {
+            Event::Whisper(..) | Event::Gap(..) => return None,
+            Event::Message(ev) => &ev.channel,
+            Event::Subscription(ev) => &ev.channel,
+            Event::Raid(ev) => &ev.channel,
+            Event::Timeout(ev) => &ev.channel,
+            Event::Ban(ev) => &ev.channel,
+            Event::Clear(ev) => &ev.channel,
+            Event::Delete(ev) => &ev.channel,
+            Event::RoomState(ev) => &ev.channel,
+            Event::Join(ev) | Event::Part(ev) => &ev.channel,
+        };
+        Some(channel)
+    }
+}
+
+impl From<Message> for Event {
+    fn from(msg: Message) -> Self {
+        Event::Message(msg)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SubKind {
+    Sub,
+    Resub,
+    /// `name` gifted a sub to `recipient`
+    Gift,
+    /// `name` gifted `gift_count` subs to random users
+    MysteryGift,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Subscription {
+    pub channel: String,
+    pub kind: SubKind,
+    pub userid: String,
+    pub name: String,
+    /// `Prime`, `1000`, `2000` or `3000`
+    pub plan: String,
+    pub months: u64,
+    pub recipient: Option<String>,
+    pub gift_count: Option<u64>,
+    /// The message the user attached, if any
+    pub data: Option<String>,
+    pub tags: Tags,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Raid {
+    pub channel: String,
+    pub name: String,
+    pub viewers: u64,
+    pub tags: Tags,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Timeout {
+    pub channel: String,
+    pub userid: String,
+    pub name: String,
+    /// In seconds
+    pub duration: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Ban {
+    pub channel: String,
+    pub userid: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Clear {
+    pub channel: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Delete {
+    pub channel: String,
+    pub name: String,
+    /// The `id` tag of the deleted message
+    pub id: String,
+    pub data: String,
+}
+
+/// Room settings. Twitch only sends the settings that changed, so the rest are `None`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RoomState {
+    pub channel: String,
+    pub emote_only: Option<bool>,
+    /// Minimum follow time in minutes, `-1` when disabled
+    pub followers_only: Option<i64>,
+    pub r9k: Option<bool>,
+    /// Seconds between messages, `0` when disabled
+    pub slow: Option<u64>,
+    pub subs_only: Option<bool>,
+}
+
+impl RoomState {
+    #[cfg(feature = "twitch")]
+    fn from_tags(channel: String, tags: &Tags) -> Self {
+        let flag = |key| tags.get(key).map(|s| s != "0");
+        Self {
+            channel,
+            emote_only: flag("emote-only"),
+            followers_only: tags.get("followers-only").and_then(|s| s.parse().ok()),
+            r9k: flag("r9k"),
+            slow: tags.get("slow").and_then(|s| s.parse().ok()),
+            subs_only: flag("subs-only"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Membership {
+    pub channel: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Whisper {
+    pub userid: String,
+    pub name: String,
+    pub data: String,
+}
+
+/// A client resumed after `after`, but some of the events since then are gone. Either they were
+/// evicted from the daemon's backlog, or the daemon restarted and its numbering began again,
+/// in which case `next` is lower than `after`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Gap {
+    /// The sequence id the client asked to resume after
+    pub after: u64,
+    /// The sequence id of the first event that will be sent
+    pub next: u64,
+}
+
+#[cfg(feature = "twitch")]
+fn channel(s: &str) -> String {
+    s.trim_start_matches('#').to_string()
+}
+
+#[cfg(feature = "twitch")]
+fn user_notice(msg: &crate::twitch::commands::UserNotice) -> Option<Event> {
+    let tags = Tags::from(msg.tags());
+    let get = |key| tags.get(key).unwrap_or_default().to_string();
+    let num = |key| tags.get(key).and_then(|s| s.parse().ok());
+
+    let kind = match tags.get("msg-id")? {
+        "raid" => {
+            return Some(Event::Raid(Raid {
+                channel: channel(msg.channel()),
+                name: get("msg-param-displayName"),
+                viewers: num("msg-param-viewerCount").unwrap_or_default(),
+                tags: tags.clone(),
+            }))
+        }
+        "sub" => SubKind::Sub,
+        "resub" => SubKind::Resub,
+        "subgift" | "anonsubgift" => SubKind::Gift,
+        "submysterygift" | "anonsubmysterygift" => SubKind::MysteryGift,
+        _ => return None,
+    };
+
+    Some(Event::Subscription(Subscription {
+        channel: channel(msg.channel()),
+        kind,
+        userid: get("user-id"),
+        name: tags
+            .get("display-name")
+            .or_else(|| tags.get("login"))
+            .unwrap_or_default()
+            .to_string(),
+        plan: get("msg-param-sub-plan"),
+        months: num("msg-param-cumulative-months")
+            .or_else(|| num("msg-param-months"))
+            .unwrap_or(1),
+        recipient: tags
+            .get("msg-param-recipient-display-name")
+            .map(ToString::to_string),
+        gift_count: num("msg-param-mass-gift-count"),
+        data: msg.message().map(ToString::to_string),
+        tags: tags.clone(),
+    }))
+}
+
+#[cfg(feature = "twitch")]
+fn clear_chat(msg: &crate::twitch::commands::ClearChat) -> Event {
+    let channel = channel(msg.channel());
+    let (name, userid) = match msg.user() {
+        Some(name) => (
+            name.to_string(),
+            msg.tags()
+                .get("target-user-id")
+                .unwrap_or_default()
+                .to_string(),
+        ),
+        None => return Event::Clear(Clear { channel }),
+    };
+
+    match msg.tags().get("ban-duration").and_then(|s| s.parse().ok()) {
+        Some(duration) => Event::Timeout(Timeout {
+            channel,
+            userid,
+            name,
+            duration,
+        }),
+        None => Event::Ban(Ban {
+            channel,
+            userid,
+            name,
+        }),
+    }
+}
+
+#[cfg(feature = "twitch")]
+fn whisper(msg: crate::twitch::irc::Message) -> Option<Event> {
+    use crate::twitch::irc::{Message, Prefix};
+    match msg {
+        Message::Unknown {
+            prefix: Some(Prefix::User { nick, .. }),
+            tags,
+            head,
+            tail: Some(data),
+            ..
+        } if head == "WHISPER" => Some(Event::Whisper(Whisper {
+            userid: tags.get("user-id").unwrap_or_default().to_string(),
+            name: tags.get("display-name").unwrap_or(&nick).to_string(),
+            data,
+        })),
+        _ => None,
+    }
+}
@@ -0,0 +1,784 @@
+//! A small expression language for matching chat messages
+//!
+//! ```text
+//! badge:moderator or (user:foo and text~"(?i)clip")
+//! channel:museun and not is_action
+//! bits>=100
+//! ```
+//!
+//! term | matches when
+//! --- | ---
+//! `badge:NAME` | the user has the badge, e.g. `moderator`, `subscriber`, `vip`
+//! `user:NAME` | the display name is `NAME`, ignoring case
+//! `userid:ID` | the user-id is `ID`
+//! `channel:NAME` | the message was sent to the channel
+//! `text:WORDS` | the message contains `WORDS`, ignoring case
+//! `emote:ID` | the message has the emote
+//! `text~REGEX`, `user~REGEX` | the regex matches the message or display name
+//! `bits>N` | the message cheered more than `N` bits. `<`, `<=`, `>=`, `=` and `!=` work too
+//! `role>=ROLE`, `role:ROLE` | the user's [`Role`](../enum.Role.html) is at least, or is, `ROLE`
+//! `months>=N` | the user has been subscribed for at least `N` months
+//! `is_action`, `first_msg`, `reply` | the message is a `/me`, the user's first, or a reply
+//!
+//! Terms are combined with `and`, `or` and `not` (or `&&`, `||` and `!`), `and` binds tighter
+//! than `or` and parentheses group. Values with spaces or any of `():~<>=!"&|` in them must be
+//! quoted, e.g. `text:"hello world"`
+use crate::{Event, Message, Role, Transport};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A parsed filter expression
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let tokens = lex(input)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.chars().count(),
+            depth: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Err(ParseError::new(0, ErrorKind::Empty));
+        }
+
+        let expr = parser.expr()?;
+        if let Some((pos, token)) = parser.peek() {
+            let kind = match token {
+                Token::RParen => ErrorKind::UnmatchedParen,
+                token => ErrorKind::Unexpected(token.to_string()),
+            };
+            return Err(ParseError::new(*pos, kind));
+        }
+
+        Ok(Self {
+            source: input.to_string(),
+            expr,
+        })
+    }
+
+    /// The expression this was parsed from
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, msg: &Message) -> bool {
+        self.expr.eval(&Subject {
+            channel: Some(&msg.channel),
+            msg: Some(msg),
+        })
+    }
+
+    /// Matches any event. Events other than messages only have a channel, so terms about the
+    /// user or the text don't match them
+    pub fn matches_event(&self, event: &Event) -> bool {
+        match event {
+            Event::Message(msg) => self.matches(msg),
+            event => self.expr.eval(&Subject {
+                channel: event.channel(),
+                msg: None,
+            }),
+        }
+    }
+}
+
+impl std::str::FromStr for Filter {
+    type Err = ParseError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::parse(input)
+    }
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Serialize for Filter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Filter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Self::parse(&source).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Filter {
+    fn schema_name() -> String {
+        "Filter".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::*;
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "A filter expression, e.g. `badge:moderator or bits>=100`".to_string(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// A transport that only gets the events matching a filter
+#[derive(Debug)]
+pub struct Filtered<T> {
+    transport: T,
+    filter: Filter,
+}
+
+impl<T: Transport> Filtered<T> {
+    pub fn new(transport: T, filter: Filter) -> Self {
+        Self { transport, filter }
+    }
+}
+
+impl<T: Transport> Transport for Filtered<T> {
+    fn send(&mut self, data: Message) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.filter.matches(&data) {
+            return Ok(());
+        }
+        self.transport.send(data)
+    }
+
+    fn send_event(&mut self, event: Event) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.filter.matches_event(&event) {
+            return Ok(());
+        }
+        self.transport.send_event(event)
+    }
+
+    fn send_sequenced(&mut self, seq: u64, event: Event) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.filter.matches_event(&event) {
+            return Ok(());
+        }
+        self.transport.send_sequenced(seq, event)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// The character offset in the input
+    pub position: usize,
+    pub kind: ErrorKind,
+}
+
+impl ParseError {
+    fn new(position: usize, kind: ErrorKind) -> Self {
+        Self { position, kind }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    Empty,
+    UnexpectedEnd,
+    Unexpected(String),
+    UnterminatedString,
+    UnmatchedParen,
+    UnclosedParen,
+    UnknownField(String),
+    /// The field needs a value, e.g. `badge` instead of `badge:vip`
+    MissingValue(String),
+    /// The field can't be used with that operator, e.g. `badge>1`
+    InvalidOperator {
+        field: String,
+        op: String,
+    },
+    InvalidNumber(String),
+    InvalidRole(String),
+    InvalidRegex(String),
+    /// More than [`MAX_DEPTH`](./constant.MAX_DEPTH.html) parentheses or `not`s inside each other
+    TooDeep,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ErrorKind::*;
+        match &self.kind {
+            Empty => write!(f, "empty filter")?,
+            UnexpectedEnd => write!(f, "unexpected end of filter")?,
+            Unexpected(token) => write!(f, "unexpected `{}`", token)?,
+            UnterminatedString => write!(f, "unterminated string")?,
+            UnmatchedParen => write!(f, "`)` without a matching `(`")?,
+            UnclosedParen => write!(f, "`(` is never closed")?,
+            UnknownField(field) => write!(f, "unknown field `{}`", field)?,
+            MissingValue(field) => write!(f, "`{}` needs a value, e.g. `{}:value`", field, field)?,
+            InvalidOperator { field, op } => write!(f, "`{}` cannot be used with `{}`", field, op)?,
+            InvalidNumber(num) => write!(f, "invalid number `{}`", num)?,
+            InvalidRole(role) => write!(f, "invalid role `{}`", role)?,
+            InvalidRegex(err) => write!(f, "invalid regex: {}", err)?,
+            TooDeep => write!(f, "nested more than {} deep", MAX_DEPTH)?,
+        }
+        write!(f, " at column {}", self.position + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    LParen,
+    RParen,
+    Colon,
+    Tilde,
+    Cmp(Cmp),
+    And,
+    Or,
+    Not,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Colon => write!(f, ":"),
+            Token::Tilde => write!(f, "~"),
+            Token::Cmp(cmp) => write!(f, "{}", cmp),
+            Token::And => write!(f, "and"),
+            Token::Or => write!(f, "or"),
+            Token::Not => write!(f, "not"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    fn compare<T: Ord>(self, left: T, right: T) -> bool {
+        match self {
+            Cmp::Eq => left == right,
+            Cmp::Ne => left != right,
+            Cmp::Lt => left < right,
+            Cmp::Le => left <= right,
+            Cmp::Gt => left > right,
+            Cmp::Ge => left >= right,
+        }
+    }
+}
+
+impl std::fmt::Display for Cmp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Cmp::Eq => "=",
+            Cmp::Ne => "!=",
+            Cmp::Lt => "<",
+            Cmp::Le => "<=",
+            Cmp::Gt => ">",
+            Cmp::Ge => ">=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+const SPECIAL: &str = "():~<>=!\"&|";
+
+fn lex(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().enumerate().peekable();
+
+    while let Some((pos, ch)) = chars.next() {
+        let mut next_is = |expected| chars.next_if(|&(_, c)| c == expected).is_some();
+
+        let token = match ch {
+            ch if ch.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ':' => Token::Colon,
+            '~' => Token::Tilde,
+            '=' => {
+                next_is('=');
+                Token::Cmp(Cmp::Eq)
+            }
+            '<' if next_is('=') => Token::Cmp(Cmp::Le),
+            '<' => Token::Cmp(Cmp::Lt),
+            '>' if next_is('=') => Token::Cmp(Cmp::Ge),
+            '>' => Token::Cmp(Cmp::Gt),
+            '!' if next_is('=') => Token::Cmp(Cmp::Ne),
+            '!' => Token::Not,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '&' | '|' => return Err(ParseError::new(pos, ErrorKind::Unexpected(ch.to_string()))),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, ch)) => s.push(ch),
+                            None => break,
+                        },
+                        Some((_, ch)) => s.push(ch),
+                        None => {
+                            return Err(ParseError::new(pos, ErrorKind::UnterminatedString));
+                        }
+                    }
+                }
+                Token::Str(s)
+            }
+            ch => {
+                let mut word = ch.to_string();
+                while let Some((_, ch)) =
+                    chars.next_if(|&(_, c)| !c.is_whitespace() && !SPECIAL.contains(c))
+                {
+                    word.push(ch)
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push((pos, token));
+    }
+
+    Ok(tokens)
+}
+
+// `and`s and `or`s are kept flat, so long chains of them don't nest
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Badge(String),
+    User(String),
+    UserId(String),
+    Channel(String),
+    Text(String),
+    Emote(String),
+    UserRegex(Regex),
+    TextRegex(Regex),
+    Bits(Cmp, u64),
+    Role(Cmp, Role),
+    Months(Cmp, u64),
+    IsAction,
+    FirstMsg,
+    Reply,
+}
+
+struct Subject<'a> {
+    channel: Option<&'a str>,
+    msg: Option<&'a Message>,
+}
+
+impl Expr {
+    fn eval(&self, subject: &Subject<'_>) -> bool {
+        match self {
+            Expr::And(exprs) => exprs.iter().all(|expr| expr.eval(subject)),
+            Expr::Or(exprs) => exprs.iter().any(|expr| expr.eval(subject)),
+            Expr::Not(expr) => !expr.eval(subject),
+            Expr::Term(term) => term.eval(subject),
+        }
+    }
+}
+
+impl Term {
+    fn eval(&self, subject: &Subject<'_>) -> bool {
+        if let Term::Channel(channel) = self {
+            return match subject.channel {
+                Some(c) => c.eq_ignore_ascii_case(channel),
+                None => false,
+            };
+        }
+
+        let msg = match subject.msg {
+            Some(msg) => msg,
+            None => return false,
+        };
+
+        match self {
+            Term::Badge(kind) => msg.badges.iter().any(|b| b.kind.eq_ignore_ascii_case(kind)),
+            Term::User(name) => msg.name.eq_ignore_ascii_case(name),
+            Term::UserId(id) => msg.userid == *id,
+            Term::Text(text) => msg.data.to_lowercase().contains(text),
+            Term::Emote(id) => msg.emotes.iter().any(|e| e.id == *id),
+            Term::UserRegex(re) => re.is_match(&msg.name),
+            Term::TextRegex(re) => re.is_match(&msg.data),
+            Term::Bits(cmp, amount) => cmp.compare(msg.bits.unwrap_or_default(), *amount),
+            Term::Role(cmp, role) => cmp.compare(msg.role(), *role),
+            Term::Months(cmp, months) => cmp.compare(msg.sub_months().unwrap_or_default(), *months),
+            Term::IsAction => msg.is_action,
+            Term::FirstMsg => msg.first_msg,
+            Term::Reply => msg.reply_to.is_some(),
+            Term::Channel(..) => unreachable!(),
+        }
+    }
+}
+
+/// How deep parentheses and `not`s can be nested
+pub const MAX_DEPTH: usize = 32;
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    // for errors at the end of the input
+    end: usize,
+    // how many `not`s and parentheses the parser is in, each of them recurses
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(usize, Token)> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), ParseError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ParseError::new(self.end, ErrorKind::UnexpectedEnd))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        match self.peek() {
+            Some((_, t)) if t == token => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // or := and ("or" and)*
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = vec![self.and()?];
+        while self.eat(&Token::Or) {
+            exprs.push(self.and()?);
+        }
+        Ok(flatten(exprs, Expr::Or))
+    }
+
+    // and := unary ("and" unary)*
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut exprs = vec![self.unary()?];
+        while self.eat(&Token::And) {
+            exprs.push(self.unary()?);
+        }
+        Ok(flatten(exprs, Expr::And))
+    }
+
+    // unary := "not" unary | "(" or ")" | term
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let (pos, token) = self.next()?;
+        match token {
+            Token::Not => self.nested(pos, |this| Ok(Expr::Not(Box::new(this.unary()?)))),
+            Token::LParen => self.nested(pos, |this| {
+                let expr = this.expr()?;
+                if !this.eat(&Token::RParen) {
+                    return Err(ParseError::new(pos, ErrorKind::UnclosedParen));
+                }
+                Ok(expr)
+            }),
+            Token::Word(field) => self.term(pos, field).map(Expr::Term),
+            Token::RParen => Err(ParseError::new(pos, ErrorKind::UnmatchedParen)),
+            token => Err(ParseError::new(
+                pos,
+                ErrorKind::Unexpected(token.to_string()),
+            )),
+        }
+    }
+
+    fn nested<F>(&mut self, pos: usize, parse: F) -> Result<Expr, ParseError>
+    where
+        F: FnOnce(&mut Self) -> Result<Expr, ParseError>,
+    {
+        if self.depth == MAX_DEPTH {
+            return Err(ParseError::new(pos, ErrorKind::TooDeep));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn term(&mut self, pos: usize, field: String) -> Result<Term, ParseError> {
+        let op = match self.peek() {
+            Some((_, Token::Colon)) | Some((_, Token::Tilde)) | Some((_, Token::Cmp(..))) => {
+                self.next()?.1
+            }
+            _ => return flag(pos, field),
+        };
+
+        let (value_pos, value) = match self.next()? {
+            (pos, Token::Word(value)) | (pos, Token::Str(value)) => (pos, value),
+            (pos, token) => {
+                return Err(ParseError::new(
+                    pos,
+                    ErrorKind::Unexpected(token.to_string()),
+                ))
+            }
+        };
+
+        let regex = |value: &str| {
+            Regex::new(value).map_err(|err| {
+                ParseError::new(value_pos, ErrorKind::InvalidRegex(regex_error(err)))
+            })
+        };
+
+        let number = |value: String| {
+            value
+                .parse()
+                .map_err(|_| ParseError::new(value_pos, ErrorKind::InvalidNumber(value)))
+        };
+
+        let term = match (field.as_str(), &op) {
+            ("badge", Token::Colon) => Term::Badge(value),
+            ("user", Token::Colon) => Term::User(value),
+            ("userid", Token::Colon) => Term::UserId(value),
+            ("channel", Token::Colon) => Term::Channel(value.trim_start_matches('#').to_string()),
+            ("text", Token::Colon) => Term::Text(value.to_lowercase()),
+            ("emote", Token::Colon) => Term::Emote(value),
+            ("user", Token::Tilde) => Term::UserRegex(regex(&value)?),
+            ("text", Token::Tilde) => Term::TextRegex(regex(&value)?),
+            ("bits", Token::Cmp(cmp)) => Term::Bits(*cmp, number(value)?),
+            ("months", Token::Cmp(cmp)) => Term::Months(*cmp, number(value)?),
+            ("role", Token::Colon) | ("role", Token::Cmp(..)) => {
+                let cmp = match op {
+                    Token::Cmp(cmp) => cmp,
+                    _ => Cmp::Eq,
+                };
+                let role = value
+                    .parse()
+                    .map_err(|_| ParseError::new(value_pos, ErrorKind::InvalidRole(value)))?;
+                Term::Role(cmp, role)
+            }
+            (field, op) if is_field(field) => {
+                let kind = ErrorKind::InvalidOperator {
+                    field: field.to_string(),
+                    op: op.to_string(),
+                };
+                return Err(ParseError::new(pos, kind));
+            }
+            (field, ..) => return Err(ParseError::new(pos, ErrorKind::UnknownField(field.into()))),
+        };
+        Ok(term)
+    }
+}
+
+fn flatten(mut exprs: Vec<Expr>, join: fn(Vec<Expr>) -> Expr) -> Expr {
+    if exprs.len() == 1 {
+        exprs.remove(0)
+    } else {
+        join(exprs)
+    }
+}
+
+fn flag(pos: usize, field: String) -> Result<Term, ParseError> {
+    match field.as_str() {
+        "is_action" => Ok(Term::IsAction),
+        "first_msg" => Ok(Term::FirstMsg),
+        "reply" => Ok(Term::Reply),
+        field if is_field(field) => {
+            Err(ParseError::new(pos, ErrorKind::MissingValue(field.into())))
+        }
+        _ => Err(ParseError::new(pos, ErrorKind::UnknownField(field))),
+    }
+}
+
+// the syntax errors are drawn over several lines, only the last one has the reason
+fn regex_error(err: regex::Error) -> String {
+    let err = err.to_string();
+    let reason = err.lines().last().unwrap_or_default();
+    reason.trim_start_matches("error: ").to_string()
+}
+
+fn is_field(field: &str) -> bool {
+    [
+        "badge", "user", "userid", "channel", "text", "emote", "bits", "role", "months",
+    ]
+    .contains(&field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Badge, Color, Tags, Version};
+
+    fn message(name: &str, data: &str, badges: &str) -> Message {
+        Message {
+            version: Version::CURRENT,
+            userid: "1004".into(),
+            timestamp: 0,
+            channel: "museun".into(),
+            id: None,
+            room_id: None,
+            name: name.into(),
+            data: data.into(),
+            color: Color::default(),
+            custom_color: None,
+            is_action: false,
+            badges: Badge::parse_list(badges),
+            emotes: vec![],
+            reply_to: None,
+            bits: None,
+            first_msg: false,
+            tags: Tags::default(),
+        }
+    }
+
+    fn matches(filter: &str, msg: &Message) -> bool {
+        Filter::parse(filter).unwrap().matches(msg)
+    }
+
+    fn error(filter: &str) -> (usize, ErrorKind) {
+        let err = Filter::parse(filter).unwrap_err();
+        (err.position, err.kind)
+    }
+
+    #[test]
+    fn terms() {
+        let mut msg = message("Foo", "Hello World", "moderator/1,subscriber/12");
+        msg.bits = Some(100);
+        msg.is_action = true;
+
+        assert!(matches("badge:moderator", &msg));
+        assert!(!matches("badge:vip", &msg));
+        assert!(matches("user:foo", &msg));
+        assert!(matches("userid:1004", &msg));
+        assert!(matches("channel:#museun", &msg));
+        assert!(matches(r#"text:"hello world""#, &msg));
+        assert!(matches(r#"text~"^Hello""#, &msg));
+        assert!(!matches(r#"user~"^f""#, &msg));
+        assert!(matches("bits>=100 && bits<101", &msg));
+        assert!(!matches("bits!=100", &msg));
+        assert!(matches("role>=vip", &msg));
+        assert!(matches("role:moderator", &msg));
+        assert!(matches("is_action", &msg));
+        assert!(!matches("first_msg || reply", &msg));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let msg = message("foo", "hi", "");
+        assert!(matches("user:foo or badge:vip and badge:moderator", &msg));
+        assert!(!matches(
+            "(user:foo or badge:vip) and badge:moderator",
+            &msg
+        ));
+        assert!(matches("not badge:vip and !is_action", &msg));
+        assert!(!matches("not (user:foo)", &msg));
+    }
+
+    #[test]
+    fn events_only_match_channels() {
+        let join = Event::Join(crate::event::Membership {
+            channel: "museun".into(),
+            name: "foo".into(),
+        });
+        let matches = |filter| Filter::parse(filter).unwrap().matches_event(&join);
+        assert!(matches("channel:MUSEUN"));
+        assert!(!matches("user:foo"));
+        assert!(matches("channel:museun and not user:foo"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("   "), (0, ErrorKind::Empty));
+        assert_eq!(error("badge:"), (6, ErrorKind::UnexpectedEnd));
+        assert_eq!(error("(badge:vip"), (0, ErrorKind::UnclosedParen));
+        assert_eq!(error("badge:vip)"), (9, ErrorKind::UnmatchedParen));
+        assert_eq!(error(r#"text:"hi"#), (5, ErrorKind::UnterminatedString));
+        assert_eq!(
+            error("colour:red"),
+            (0, ErrorKind::UnknownField("colour".into()))
+        );
+        assert_eq!(
+            error("is_action or badge"),
+            (13, ErrorKind::MissingValue("badge".into()))
+        );
+        assert_eq!(error("bits>x"), (5, ErrorKind::InvalidNumber("x".into())));
+        assert_eq!(
+            error("role>=owner"),
+            (6, ErrorKind::InvalidRole("owner".into()))
+        );
+        assert_eq!(error("badge & vip"), (6, ErrorKind::Unexpected("&".into())));
+        assert!(matches!(
+            error(r#"text~"(""#),
+            (5, ErrorKind::InvalidRegex(..))
+        ));
+        assert_eq!(
+            error("badge>1"),
+            (
+                0,
+                ErrorKind::InvalidOperator {
+                    field: "badge".into(),
+                    op: ">".into()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| format!("{}is_action{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Filter::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            error(&nested(MAX_DEPTH + 1)),
+            (MAX_DEPTH, ErrorKind::TooDeep)
+        );
+        assert_eq!(error(&"!".repeat(4000)), (MAX_DEPTH, ErrorKind::TooDeep));
+        assert_eq!(error(&"(".repeat(4000)), (MAX_DEPTH, ErrorKind::TooDeep));
+    }
+
+    #[test]
+    fn long_chains() {
+        let mut msg = message("museun", "waves", "");
+        msg.is_action = true;
+        // these don't nest, so they aren't limited by MAX_DEPTH
+        let or = vec!["is_action"; 30000].join(" or ");
+        assert!(matches(&or, &msg));
+        let and = vec!["!reply"; 30000].join(" && ");
+        assert!(matches(&and, &msg));
+        let mixed = vec!["reply and is_action"; 30000].join(" or ");
+        assert!(!matches(&mixed, &msg));
+    }
+
+    #[test]
+    fn round_trips_as_a_string() {
+        let filter = Filter::parse("badge:vip or bits>10").unwrap();
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(json, r#""badge:vip or bits>10""#);
+        let filter: Filter = serde_json::from_str(&json).unwrap();
+        assert_eq!(filter.as_str(), "badge:vip or bits>10");
+        assert!(serde_json::from_str::<Filter>(r#""badge:""#).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! The optional handshake at the start of a socket connection
+//!
+//! Right after connecting, a client can send a [`Hello`](./struct.Hello.html) as a single JSON
+//! line. The daemon answers with a [`Reply`](./enum.Reply.html), as the first frame in the
+//! encoding it picked, and the events follow.
+//!
+//! ```text
+//! > {"versions":[2],"encoding":"json","channels":["museun"],"events":["message"],"resume":1041}
+//! < {"welcome":{"version":2,"encoding":"json","channels":["museun"],"events":["message"],"resume":1041}}
+//! < {"seq":1042,"event":"message",...}
+//! ```
+//!
+//! Every field of the hello can be left out. Without `versions` the daemon still uses the rest,
+//! but doesn't reply, which is how `{"resume":N}` works.
+//!
+//! Clients that send nothing get what the daemon sent before any of this, so older clients keep
+//! working. That is version 1: the chat messages in the backlog, as bare
+//! [`Message`](../struct.Message.html)s with a string `timestamp` and twitchchat's colors and
+//! badges. There is no reply, no `seq` or `event` field and no other events
+use crate::client::Error;
+use crate::filter::Filter;
+use crate::{Encoding, Event, Version};
+use serde::{Deserialize, Serialize};
+
+/// What a client asks for
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Hello {
+    /// The versions the client understands. The daemon picks one of these, or rejects the client
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<Version>,
+    /// The encoding to send events in, instead of the socket's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
+    /// Only send the events from these channels. Empty is all of them. Whispers aren't sent to
+    /// a channel, so they are always sent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    /// Only send these events, by the name in their `event` field. Empty is all of them.
+    /// Gaps are always sent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    /// Only send the events matching this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    /// Only send the events after this sequence id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<u64>,
+}
+
+impl Hello {
+    /// A hello for the current version, asking for nothing else
+    pub fn new() -> Self {
+        Self {
+            versions: vec![Version::CURRENT],
+            ..Self::default()
+        }
+    }
+
+    /// The hello as it is sent, a single JSON line
+    pub fn to_line(&self) -> Vec<u8> {
+        let mut line = serde_json::to_vec(self).expect("valid json");
+        line.push(b'\n');
+        line
+    }
+}
+
+/// The daemon's answer to a [`Hello`](./struct.Hello.html)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Reply {
+    Welcome(Welcome),
+    /// The daemon can't serve the client, and closes the connection after this
+    Rejected(String),
+}
+
+/// What the daemon picked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Welcome {
+    pub version: Version,
+    pub encoding: Encoding,
+    /// The channels, without the leading `#` and lowercased. Empty is all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    /// The events that will be sent, without the names the daemon didn't know. Empty is all.
+    /// A hello with only unknown names is rejected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<u64>,
+}
+
+// gaps are always wanted, an empty list of channels or events is all of them
+pub(crate) fn is_wanted(
+    channels: &[String],
+    events: &[String],
+    filter: Option<&Filter>,
+    event: &Event,
+) -> bool {
+    if let Event::Gap(..) = event {
+        return true;
+    }
+    is_subscribed(channels, event)
+        && (events.is_empty() || events.iter().any(|name| name == event.name()))
+        && filter.map_or(true, |filter| filter.matches_event(event))
+}
+
+// whispers and version 1 messages have no channel, so they always match
+fn is_subscribed(channels: &[String], event: &Event) -> bool {
+    match event.channel() {
+        Some(channel) if !channels.is_empty() && !channel.is_empty() => {
+            channels.iter().any(|c| c == channel)
+        }
+        _ => true,
+    }
+}
+
+// where a client is in the handshake
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Handshake {
+    // the hello goes out before the first read
+    Hello,
+    // the first frame is the reply, unless the daemon is older than the handshake
+    Reply,
+    Done,
+}
+
+// the reply, if `frame` is one. a rejection is an error
+pub(crate) fn read_reply(encoding: Encoding, frame: &[u8]) -> Result<Option<Welcome>, Error> {
+    match encoding.decode_frame::<Reply>(frame) {
+        Some(Reply::Welcome(welcome)) => Ok(Some(welcome)),
+        Some(Reply::Rejected(reason)) => Err(Error::Rejected(reason)),
+        None => Ok(None),
+    }
+}
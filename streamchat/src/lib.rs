@@ -1,20 +1,71 @@
 mod message;
-pub use self::message::{Message, Version};
+pub use self::message::{ConversionError, Ctcp, Message, Reply, Version};
+
+pub mod types;
+pub use self::types::{Badge, Color, Emote, Rgb, Role, Tags};
+
+pub mod richtext;
+
+pub mod event;
+pub use self::event::Event;
 
 mod queue;
 pub use self::queue::Queue;
 
+pub mod encoding;
+pub use self::encoding::Encoding;
+
+pub mod handshake;
+
+#[cfg(feature = "schema")]
+pub mod schema;
+
 pub trait Transport: Send {
-    fn send(&mut self, data: Message) -> Result<(), Box<std::error::Error>>;
+    fn send(&mut self, data: Message) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Sends any chat event. By default only messages are forwarded to `send`
+    fn send_event(&mut self, event: Event) -> Result<(), Box<dyn std::error::Error>> {
+        match event {
+            Event::Message(msg) => self.send(msg),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends an event with the sequence id the daemon gave it. The ids only go up, so clients
+    /// can resume after the last one they saw. By default the id is dropped
+    fn send_sequenced(&mut self, seq: u64, event: Event) -> Result<(), Box<dyn std::error::Error>> {
+        let _ = seq;
+        self.send_event(event)
+    }
 }
 
 /// Re-export of [`twitchchat`](https://docs.rs/twitchchat) to make it a direct dependency
+#[cfg(feature = "twitch")]
 pub mod twitch {
     pub use twitchchat::*;
 }
 
+#[cfg(feature = "twitch")]
 pub mod connection;
 
+#[cfg(feature = "daemon")]
+pub mod daemon;
+
+pub mod client;
+
+pub mod filter;
+
+pub mod outbound;
+
+#[cfg(feature = "async")]
+pub mod nonblocking;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(any(feature = "twitch", feature = "testing"))]
+mod rng;
+
 #[inline]
 pub fn make_timestamp() -> u64 {
     std::time::SystemTime::now()
@@ -22,3 +73,8 @@ pub fn make_timestamp() -> u64 {
         .expect("valid system time")
         .as_millis() as u64
 }
+
+// channel names without the leading `#`, lowercased, as they appear on events
+pub(crate) fn normalize_channel(channel: &str) -> String {
+    channel.trim().trim_start_matches('#').to_lowercase()
+}
@@ -1,21 +1,41 @@
-use crate::twitch::{self, Badge, Color, Emotes, Tags};
-use serde::{Deserialize, Serialize};
+use crate::richtext::{self, Segment};
+use crate::types::{Badge, Color, Emote, Role, Tags};
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Version(pub u8);
 
+impl Version {
+    /// The newest version this crate understands
+    pub const CURRENT: Version = Version(2);
+}
+
 impl Default for Version {
     fn default() -> Self {
-        Version(1)
+        Version::CURRENT
     }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Message {
     pub version: Version,
 
     pub userid: String,
-    pub timestamp: String,
+    /// Milliseconds since the unix epoch. This is when Twitch saw the message, if it told us
+    #[serde(deserialize_with = "timestamp")]
+    pub timestamp: u64,
+
+    /// The channel, without the leading `#`
+    #[serde(default)]
+    pub channel: String,
+    /// Twitch's id for this message
+    #[serde(default)]
+    pub id: Option<String>,
+    /// The user-id of the channel
+    #[serde(default)]
+    pub room_id: Option<u64>,
 
     pub name: String,
     pub data: String,
@@ -26,36 +46,428 @@ pub struct Message {
     pub is_action: bool,
 
     pub badges: Vec<Badge>,
-    pub emotes: Vec<Emotes>,
+    pub emotes: Vec<Emote>,
+
+    /// The message this is a reply to
+    #[serde(default)]
+    pub reply_to: Option<Reply>,
+    /// Bits cheered with this message
+    #[serde(default)]
+    pub bits: Option<u64>,
+    /// Whether this is the user's first message in the channel
+    #[serde(default)]
+    pub first_msg: bool,
 
     pub tags: Tags,
 }
 
-impl From<twitch::commands::PrivMsg> for Message {
-    fn from(msg: twitch::commands::PrivMsg) -> Self {
-        let timestamp = crate::make_timestamp().to_string();
+impl Message {
+    /// Splits `data` into text, emotes, mentions, urls and, if bits were attached, cheers.
+    ///
+    /// For actions (`/me`) Twitch's emote ranges are already relative to `data`
+    pub fn segments(&self) -> Vec<Segment<'_>> {
+        let emotes = self.emotes.iter().flat_map(|emote| {
+            emote
+                .ranges
+                .iter()
+                .map(move |range| (emote.id.as_str(), range.start, range.end))
+        });
+        richtext::parse(&self.data, emotes, self.bits)
+    }
+
+    /// The user's role in the channel, from their badges and the `mod` tag
+    pub fn role(&self) -> Role {
+        let role = Role::from_badges(&self.badges);
+        match self.tags.get("mod") {
+            Some("1") => role.max(Role::Moderator),
+            _ => role,
+        }
+    }
+
+    /// How many months the user has been subscribed, from the `badge-info` tag.
+    ///
+    /// This is `None` for users who aren't subscribed, and for version 1 messages
+    pub fn sub_months(&self) -> Option<u64> {
+        let info = self.tags.get("badge-info")?;
+        Badge::parse_list(info)
+            .into_iter()
+            .find(|badge| badge.kind == "subscriber" || badge.kind == "founder")?
+            .version
+            .parse()
+            .ok()
+    }
+}
+
+#[cfg(any(feature = "daemon", feature = "schema"))]
+pub(crate) use self::v1::V1;
+
+#[cfg(any(feature = "daemon", feature = "schema"))]
+mod v1 {
+    use super::*;
+    use crate::types::{Range, Rgb, NAMED_COLORS};
+    use serde::Serializer;
+
+    // a message in the shape version 1 sent it, using twitchchat's types. clients from then
+    // deserialize into those, so only the fields and values they knew are written
+    #[derive(Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    pub(crate) struct V1<'a> {
+        version: Version,
+        userid: &'a str,
+        timestamp: String,
+        name: &'a str,
+        data: &'a str,
+        color: V1Color,
+        custom_color: Option<V1Color>,
+        is_action: bool,
+        badges: Vec<V1Badge<'a>>,
+        emotes: Vec<V1Emote>,
+        tags: &'a Tags,
+    }
+
+    impl<'a> From<&'a Message> for V1<'a> {
+        fn from(msg: &'a Message) -> Self {
+            Self {
+                version: Version(1),
+                userid: &msg.userid,
+                timestamp: msg.timestamp.to_string(),
+                name: &msg.name,
+                data: &msg.data,
+                color: V1Color(msg.color.rgb),
+                custom_color: msg.custom_color.as_ref().map(|color| V1Color(color.rgb)),
+                is_action: msg.is_action,
+                badges: msg.badges.iter().map(V1Badge).collect(),
+                // version 1 only had numeric ids
+                emotes: msg
+                    .emotes
+                    .iter()
+                    .filter_map(|emote| {
+                        Some(V1Emote {
+                            id: emote.id.parse().ok()?,
+                            ranges: emote.ranges.clone(),
+                        })
+                    })
+                    .collect(),
+                tags: &msg.tags,
+            }
+        }
+    }
+
+    // "OrangeRed" for the named colors, otherwise {"Turbo": [r, g, b]}
+    struct V1Color(Rgb);
+
+    impl Serialize for V1Color {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeMap as _;
+            if let Some((name, _)) = NAMED_COLORS.iter().find(|(_, rgb)| *rgb == self.0) {
+                return serializer.serialize_str(name);
+            }
+            let Rgb(r, g, b) = self.0;
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry("Turbo", &(r, g, b))?;
+            map.end()
+        }
+    }
+
+    #[cfg(feature = "schema")]
+    impl schemars::JsonSchema for V1Color {
+        fn schema_name() -> String {
+            "V1Color".to_string()
+        }
+
+        fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+            let names = NAMED_COLORS.iter().map(|(name, _)| *name);
+            one_of(vec![
+                names_schema(names),
+                single_key("Turbo", gen.subschema_for::<(u8, u8, u8)>()),
+            ])
+        }
+    }
+
+    // twitchchat's variant names for the badges it knew
+    const BADGE_KINDS: [(&str, &str); 11] = [
+        ("admin", "Admin"),
+        ("bits", "Bits"),
+        ("broadcaster", "Broadcaster"),
+        ("global_mod", "GlobalMod"),
+        ("moderator", "Moderator"),
+        ("subscriber", "Subscriber"),
+        ("staff", "Staff"),
+        ("turbo", "Turbo"),
+        ("premium", "Premium"),
+        ("vip", "VIP"),
+        ("partner", "Partner"),
+    ];
+
+    // twitchchat's variant names, with {"Unknown": kind} for the rest
+    struct V1Badge<'a>(&'a Badge);
+
+    impl Serialize for V1Badge<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeStruct as _;
+
+            #[derive(Serialize)]
+            enum Kind<'a> {
+                Unknown(&'a str),
+            }
+
+            let known = BADGE_KINDS
+                .iter()
+                .find(|(kind, _)| *kind == self.0.kind)
+                .map(|(_, name)| *name);
+
+            let mut badge = serializer.serialize_struct("Badge", 2)?;
+            match known {
+                Some(kind) => badge.serialize_field("kind", kind)?,
+                None => badge.serialize_field("kind", &Kind::Unknown(&self.0.kind))?,
+            }
+            badge.serialize_field("data", &self.0.version)?;
+            badge.end()
+        }
+    }
+
+    #[cfg(feature = "schema")]
+    impl schemars::JsonSchema for V1Badge<'_> {
+        fn schema_name() -> String {
+            "V1Badge".to_string()
+        }
+
+        fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+            use schemars::schema::*;
+            let names = BADGE_KINDS.iter().map(|(_, name)| *name);
+            let kind = one_of(vec![
+                names_schema(names),
+                single_key("Unknown", gen.subschema_for::<String>()),
+            ]);
+
+            let mut badge = SchemaObject {
+                instance_type: Some(InstanceType::Object.into()),
+                ..Default::default()
+            };
+            let object = badge.object();
+            object.properties.insert("kind".to_string(), kind);
+            object
+                .properties
+                .insert("data".to_string(), gen.subschema_for::<String>());
+            object.required.insert("kind".to_string());
+            object.required.insert("data".to_string());
+            badge.into()
+        }
+    }
+
+    // a string that is one of `names`
+    #[cfg(feature = "schema")]
+    fn names_schema<'a>(names: impl Iterator<Item = &'a str>) -> schemars::schema::Schema {
+        use schemars::schema::*;
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(names.map(Into::into).collect()),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    // an object with only `key`, like serde writes a newtype variant
+    #[cfg(feature = "schema")]
+    fn single_key(key: &str, value: schemars::schema::Schema) -> schemars::schema::Schema {
+        use schemars::schema::*;
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            ..Default::default()
+        };
+        let object = schema.object();
+        object.properties.insert(key.to_string(), value);
+        object.required.insert(key.to_string());
+        object.additional_properties = Some(Box::new(false.into()));
+        schema.into()
+    }
+
+    #[cfg(feature = "schema")]
+    fn one_of(schemas: Vec<schemars::schema::Schema>) -> schemars::schema::Schema {
+        use schemars::schema::*;
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(schemas),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[derive(Serialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    struct V1Emote {
+        id: u64,
+        ranges: Vec<Range>,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Reply {
+    /// The `id` of the parent message
+    pub id: String,
+    pub userid: String,
+    pub name: String,
+    pub data: String,
+}
+
+impl Reply {
+    #[cfg(feature = "twitch")]
+    fn from_tags(tags: &Tags) -> Option<Self> {
+        let get = |key| tags.get(key).map(ToString::to_string);
+        Some(Self {
+            id: get("reply-parent-msg-id")?,
+            userid: get("reply-parent-user-id").unwrap_or_default(),
+            name: get("reply-parent-display-name")
+                .or_else(|| get("reply-parent-user-login"))
+                .unwrap_or_default(),
+            data: get("reply-parent-msg-body").unwrap_or_default(),
+        })
+    }
+}
+
+// version 1 sent the timestamp as a string
+fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Number(u64),
+        String(String),
+    }
+
+    match Timestamp::deserialize(deserializer)? {
+        Timestamp::Number(ts) => Ok(ts),
+        Timestamp::String(ts) => ts.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(feature = "twitch")]
+impl std::convert::TryFrom<crate::twitch::commands::PrivMsg> for Message {
+    type Error = ConversionError;
 
-        let user_id = msg.user_id().expect("user-id");
-        let name = msg.display_name().unwrap_or_else(|| msg.user()).to_string();
+    fn try_from(msg: crate::twitch::commands::PrivMsg) -> Result<Self, Self::Error> {
+        let tags = Tags::from(msg.tags());
+        let timestamp = tags
+            .get("tmi-sent-ts")
+            .and_then(|ts| ts.parse().ok())
+            .unwrap_or_else(crate::make_timestamp);
 
-        let (data, is_action) = if msg.message().starts_with('\x01') {
-            (&msg.message()[8..msg.message().len() - 1], true)
-        } else {
-            (msg.message(), false)
+        let user_id = tags.get("user-id").ok_or(ConversionError::MissingUserId)?;
+        let name = tags
+            .get("display-name")
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| msg.user())
+            .to_string();
+
+        let (data, is_action) = match Ctcp::parse(msg.message()) {
+            Some(Ctcp::Action(data)) => (data, true),
+            Some(Ctcp::Other { command, .. }) => {
+                return Err(ConversionError::UnsupportedCtcp(command.to_string()))
+            }
+            None => (msg.message(), false),
         };
 
-        Self {
+        Ok(Self {
             version: Version::default(),
             userid: user_id.to_string(),
-            color: msg.color().unwrap_or_default(),
+            channel: msg.channel().trim_start_matches('#').to_string(),
+            id: tags.get("id").map(ToString::to_string),
+            room_id: tags.get("room-id").and_then(|id| id.parse().ok()),
+            color: tags
+                .get("color")
+                .and_then(|color| color.parse().ok())
+                .unwrap_or_default(),
             custom_color: None,
-            badges: msg.badges(),
-            emotes: msg.emotes(),
-            tags: msg.tags().clone(),
+            badges: Badge::parse_list(tags.get("badges").unwrap_or_default()),
+            emotes: Emote::parse_list(tags.get("emotes").unwrap_or_default()),
+            reply_to: Reply::from_tags(&tags),
+            bits: tags.get("bits").and_then(|bits| bits.parse().ok()),
+            first_msg: tags.get("first-msg") == Some("1"),
             timestamp,
             name,
             data: data.to_string(),
             is_action,
+            tags,
+        })
+    }
+}
+
+/// A CTCP payload, e.g. `\x01ACTION waves\x01` for `/me waves`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Ctcp<'a> {
+    Action(&'a str),
+    Other { command: &'a str, args: &'a str },
+}
+
+impl<'a> Ctcp<'a> {
+    /// Parses `data` if it is a CTCP message. The trailing `\x01` is optional
+    pub fn parse(data: &'a str) -> Option<Self> {
+        if !data.starts_with('\x01') {
+            return None;
+        }
+
+        let data = data[1..].trim_end_matches('\x01');
+        let mut parts = data.splitn(2, ' ');
+        let command = parts.next().filter(|s| !s.is_empty())?;
+        let args = parts.next().unwrap_or_default();
+
+        Some(match command {
+            "ACTION" => Ctcp::Action(args),
+            command => Ctcp::Other { command, args },
+        })
+    }
+}
+
+/// Why a `PRIVMSG` couldn't be turned into a [`Message`](./struct.Message.html)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversionError {
+    /// The message had no `user-id` tag, e.g. the tags capability wasn't requested
+    MissingUserId,
+    /// A CTCP message other than `ACTION`
+    UnsupportedCtcp(String),
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::MissingUserId => write!(f, "missing user-id"),
+            ConversionError::UnsupportedCtcp(cmd) => write!(f, "unsupported ctcp: {}", cmd),
         }
     }
 }
+
+impl std::error::Error for ConversionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ctcp() {
+        assert_eq!(
+            Ctcp::parse("\x01ACTION waves\x01"),
+            Some(Ctcp::Action("waves"))
+        );
+        // the trailing \x01 is optional
+        assert_eq!(Ctcp::parse("\x01ACTION waves"), Some(Ctcp::Action("waves")));
+        assert_eq!(Ctcp::parse("\x01ACTION\x01"), Some(Ctcp::Action("")));
+        assert_eq!(
+            Ctcp::parse("\x01VERSION\x01"),
+            Some(Ctcp::Other {
+                command: "VERSION",
+                args: ""
+            })
+        );
+        assert_eq!(
+            Ctcp::parse("\x01PING 1234 5\x01"),
+            Some(Ctcp::Other {
+                command: "PING",
+                args: "1234 5"
+            })
+        );
+        assert_eq!(Ctcp::parse("\x01\x01"), None);
+        assert_eq!(Ctcp::parse("ACTION waves"), None);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::client::Error;
+use crate::filter::Filter;
+use crate::handshake::{self, Handshake, Hello, Welcome};
+use crate::{Encoding, Event, Message};
+use futures_util::future;
+use futures_util::stream::{self, Stream, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// An async connection to `streamchatd`
+///
+/// This is the async flavour of [`client::DaemonClient`](../client/struct.DaemonClient.html)
+#[derive(Debug)]
+pub struct DaemonClient {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    channels: Vec<String>,
+    encoding: Encoding,
+    events: Vec<String>,
+    filter: Option<Filter>,
+    resume: Option<u64>,
+    handshake: Handshake,
+    welcome: Option<Welcome>,
+    last_seq: Option<u64>,
+}
+
+impl DaemonClient {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        TcpStream::connect(addr)
+            .await
+            .map(Self::from_stream)
+            .map_err(Error::Io)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buf: vec![],
+            channels: vec![],
+            encoding: Encoding::Json,
+            events: vec![],
+            filter: None,
+            resume: None,
+            handshake: Handshake::Hello,
+            welcome: None,
+            last_seq: None,
+        }
+    }
+
+    /// Only receive events from these channels, see
+    /// [`client::DaemonClient::subscribe`](../client/struct.DaemonClient.html#method.subscribe)
+    pub fn subscribe(mut self, channels: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.channels = channels
+            .into_iter()
+            .map(|channel| crate::normalize_channel(channel.as_ref()))
+            .collect();
+        self
+    }
+
+    /// The encoding the daemon writes on this connection, see
+    /// [`client::DaemonClient::encoding`](../client/struct.DaemonClient.html#method.encoding)
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Only receive these events, see
+    /// [`client::DaemonClient::event_types`](../client/struct.DaemonClient.html#method.event_types)
+    pub fn event_types(mut self, events: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.events = events
+            .into_iter()
+            .map(|event| event.as_ref().to_string())
+            .collect();
+        self
+    }
+
+    /// Only receive the events matching `filter`, see
+    /// [`client::DaemonClient::filter`](../client/struct.DaemonClient.html#method.filter)
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Only the events after `seq`, see
+    /// [`client::DaemonClient::resume`](../client/struct.DaemonClient.html#method.resume)
+    pub fn resume(mut self, seq: u64) -> Self {
+        self.resume = Some(seq);
+        self
+    }
+
+    /// The sequence id of the last event read, if the daemon sent one
+    pub fn last_seq(&self) -> Option<u64> {
+        self.last_seq
+    }
+
+    /// What the daemon picked for this connection, see
+    /// [`client::DaemonClient::welcome`](../client/struct.DaemonClient.html#method.welcome)
+    pub fn welcome(&self) -> Option<&Welcome> {
+        self.welcome.as_ref()
+    }
+
+    /// Waits for the next event
+    pub async fn read_event(&mut self) -> Result<Event, Error> {
+        if self.handshake == Handshake::Hello {
+            let hello = Hello {
+                encoding: Some(self.encoding),
+                channels: self.channels.clone(),
+                events: self.events.clone(),
+                filter: self.filter.clone(),
+                resume: self.resume,
+                ..Hello::new()
+            };
+            self.stream.write_all(&hello.to_line()).await?;
+            self.handshake = Handshake::Reply;
+        }
+        loop {
+            let frame = match self.encoding.next_frame(&mut self.buf)? {
+                Some(frame) => frame,
+                None => {
+                    let mut buf = [0; 4096];
+                    match self.stream.read(&mut buf).await? {
+                        0 => return Err(Error::Disconnected),
+                        n => self.buf.extend_from_slice(&buf[..n]),
+                    }
+                    continue;
+                }
+            };
+
+            if self.handshake == Handshake::Reply {
+                self.handshake = Handshake::Done;
+                if let Some(welcome) = handshake::read_reply(self.encoding, &frame)? {
+                    self.encoding = welcome.encoding;
+                    self.welcome = Some(welcome);
+                    continue;
+                }
+            }
+
+            let (seq, event) = self.encoding.decode_seq(&frame)?;
+            if seq.is_some() {
+                self.last_seq = seq;
+            }
+            match event {
+                event
+                    if handshake::is_wanted(
+                        &self.channels,
+                        &self.events,
+                        self.filter.as_ref(),
+                        &event,
+                    ) =>
+                {
+                    return Ok(event)
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Yields events until the daemon disconnects, or a non-recoverable error happens
+    pub fn events(self) -> impl Stream<Item = Result<Event, Error>> {
+        stream::unfold(Some(self), |client| async move {
+            let mut client = client?;
+            match client.read_event().await {
+                Ok(event) => Some((Ok(event), Some(client))),
+                Err(Error::Disconnected) => None,
+                Err(err) if err.is_recoverable() => Some((Err(err), Some(client))),
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+
+    /// Only the chat messages, skipping the other events
+    pub fn messages(self) -> impl Stream<Item = Result<Message, Error>> {
+        self.events().filter_map(|event| {
+            future::ready(match event {
+                Ok(Event::Message(msg)) => Some(Ok(msg)),
+                Ok(..) => None,
+                Err(err) => Some(Err(err)),
+            })
+        })
+    }
+}
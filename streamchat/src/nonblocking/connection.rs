@@ -0,0 +1,263 @@
+use crate::connection::{anonymous_nick, is_anonymous, validate, Error, ANONYMOUS_TOKEN};
+use crate::{twitch, Event};
+use futures_util::stream::{self, Stream};
+use std::sync::Arc;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf,
+};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::Mutex;
+
+/// Connects to Twitch, registers with `nick` and `token` then joins each of `channels`.
+///
+/// If `token` is empty this logs in anonymously, see
+/// [`connection::is_anonymous`](../connection/fn.is_anonymous.html)
+pub async fn connect_to_twitch(
+    nick: impl AsRef<str>,
+    token: impl AsRef<str>,
+    channels: &[impl AsRef<str>],
+) -> Result<Connection<TcpStream>, Error> {
+    connect_to(twitch::TWITCH_IRC_ADDRESS, nick, token, channels).await
+}
+
+/// Like [`connect_to_twitch`](./fn.connect_to_twitch.html) but connects to `addr` instead of
+/// Twitch's IRC server
+pub async fn connect_to(
+    addr: impl ToSocketAddrs + std::fmt::Debug,
+    nick: impl AsRef<str>,
+    token: impl AsRef<str>,
+    channels: &[impl AsRef<str>],
+) -> Result<Connection<TcpStream>, Error> {
+    validate(nick.as_ref(), token.as_ref(), channels)?;
+
+    log::info!("connecting to: {:?}", addr);
+    let stream = TcpStream::connect(addr).await?;
+    log::info!("opened connection");
+
+    connect_with(stream, nick, token, channels).await
+}
+
+/// Registers and joins `channels` over an already opened stream
+///
+/// This can be used for proxied or TLS streams
+pub async fn connect_with<S>(
+    stream: S,
+    nick: impl AsRef<str>,
+    token: impl AsRef<str>,
+    channels: &[impl AsRef<str>],
+) -> Result<Connection<S>, Error>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    validate(nick.as_ref(), token.as_ref(), channels)?;
+
+    let anonymous = is_anonymous(&token);
+    let (nick, token) = if anonymous {
+        (anonymous_nick(), ANONYMOUS_TOKEN)
+    } else {
+        (nick.as_ref().to_string(), token.as_ref())
+    };
+
+    let (read, write) = tokio::io::split(stream);
+    let mut conn = Connection {
+        lines: BufReader::new(read).lines(),
+        writer: Writer {
+            write: Arc::new(Mutex::new(write)),
+            anonymous,
+        },
+    };
+
+    log::info!("registering with nick: {}", nick);
+    for cap in &[
+        "twitch.tv/tags",
+        "twitch.tv/commands",
+        "twitch.tv/membership",
+    ] {
+        conn.writer.send(format!("CAP REQ :{}", cap)).await?;
+    }
+    conn.writer.send(format!("PASS {}", token)).await?;
+    conn.writer.send(format!("NICK {}", nick)).await?;
+
+    // twitch sends GLOBALUSERSTATE once it is ready for us, anonymous users only get the welcome
+    loop {
+        let line = conn.lines.next_line().await?.ok_or(Error::Disconnected)?;
+        match command(&line) {
+            ("PING", token) => conn.writer.send(format!("PONG {}", token)).await?,
+            ("NOTICE", rest) if is_login_failure(rest) => return Err(Error::InvalidLogin),
+            ("001", ..) if anonymous => {
+                log::warn!("connected anonymously as {}. sending is disabled", nick);
+                break;
+            }
+            ("GLOBALUSERSTATE", ..) => {
+                log::info!(
+                    "connected with {} ({}).",
+                    tag(&line, "display-name").unwrap_or(&nick),
+                    tag(&line, "user-id").unwrap_or_default()
+                );
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    for channel in channels {
+        let channel = crate::normalize_channel(channel.as_ref());
+        conn.writer.join(&channel).await?;
+        log::info!("joined: {}", channel);
+    }
+
+    Ok(conn)
+}
+
+/// A registered connection to Twitch
+pub struct Connection<S> {
+    lines: Lines<BufReader<ReadHalf<S>>>,
+    writer: Writer<S>,
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    /// A handle for sending to Twitch, this can be used while the events are being read
+    pub fn writer(&self) -> Writer<S> {
+        self.writer.clone()
+    }
+
+    /// Waits for the next chat [`Event`](../enum.Event.html), answering `PING`s along the way
+    pub async fn next_event(&mut self) -> Result<Event, Error> {
+        loop {
+            let line = self.lines.next_line().await?.ok_or(Error::Disconnected)?;
+            if let ("PING", token) = command(&line) {
+                self.writer.send(format!("PONG {}", token)).await?;
+                continue;
+            }
+
+            if let Some(event) = parse(&line).and_then(Event::from_twitch) {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Every chat event, until Twitch disconnects or an error happens
+    pub fn events(self) -> impl Stream<Item = Result<Event, Error>> {
+        stream::unfold(Some(self), |conn| async move {
+            let mut conn = conn?;
+            match conn.next_event().await {
+                Ok(event) => Some((Ok(event), Some(conn))),
+                Err(Error::Disconnected) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+}
+
+/// The sending half of a [`Connection`](./struct.Connection.html). It is cheap to clone
+///
+/// On an anonymous connection only joining and parting work, everything else returns
+/// `Error::ReadOnly`
+pub struct Writer<S> {
+    write: Arc<Mutex<WriteHalf<S>>>,
+    anonymous: bool,
+}
+
+impl<S> Clone for Writer<S> {
+    fn clone(&self) -> Self {
+        Writer {
+            write: Arc::clone(&self.write),
+            anonymous: self.anonymous,
+        }
+    }
+}
+
+impl<S> Writer<S>
+where
+    S: AsyncWrite,
+{
+    pub async fn join(&self, channel: impl AsRef<str>) -> Result<(), Error> {
+        self.send(format!(
+            "JOIN #{}",
+            channel.as_ref().trim_start_matches('#')
+        ))
+        .await
+    }
+
+    pub async fn part(&self, channel: impl AsRef<str>) -> Result<(), Error> {
+        self.send(format!(
+            "PART #{}",
+            channel.as_ref().trim_start_matches('#')
+        ))
+        .await
+    }
+
+    pub async fn privmsg(
+        &self,
+        channel: impl AsRef<str>,
+        data: impl AsRef<str>,
+    ) -> Result<(), Error> {
+        self.raw(format!(
+            "PRIVMSG #{} :{}",
+            channel.as_ref().trim_start_matches('#'),
+            data.as_ref()
+        ))
+        .await
+    }
+
+    /// Whether this is an anonymous connection, which can't send messages
+    pub fn is_anonymous(&self) -> bool {
+        self.anonymous
+    }
+
+    /// Sends a raw line. The line ending is added
+    pub async fn raw(&self, line: impl AsRef<str>) -> Result<(), Error> {
+        if self.anonymous {
+            return Err(Error::ReadOnly);
+        }
+        self.send(line).await
+    }
+
+    async fn send(&self, line: impl AsRef<str>) -> Result<(), Error> {
+        let mut write = self.write.lock().await;
+        write.write_all(line.as_ref().as_bytes()).await?;
+        write.write_all(b"\r\n").await?;
+        write.flush().await?;
+        Ok(())
+    }
+}
+
+// twitchchat only parses through a ReadAdapter, so the line is fed through a throwaway one
+fn parse(line: &str) -> Option<twitch::Message> {
+    let line = std::io::Cursor::new(format!("{}\r\n", line).into_bytes());
+    let (read, write) = twitch::sync_adapters(line, std::io::sink());
+    twitch::Client::new(read, write)
+        .read_message()
+        .map_err(|err| log::debug!("cannot parse line: {}", err))
+        .ok()
+}
+
+// the command and its arguments, skipping the tags and prefix
+fn command(line: &str) -> (&str, &str) {
+    let mut line = line;
+    for sigil in &['@', ':'] {
+        if line.starts_with(*sigil) {
+            line = line
+                .split_once(' ')
+                .map(|(_, rest)| rest)
+                .unwrap_or_default();
+        }
+    }
+    line.split_once(' ').unwrap_or((line, ""))
+}
+
+fn tag<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let (tags, _) = line.strip_prefix('@')?.split_once(' ')?;
+    tags.split(';')
+        .filter_map(|tag| tag.split_once('='))
+        .find(|&(k, _)| k == key)
+        .map(|(_, v)| v)
+        .filter(|v| !v.is_empty())
+}
+
+fn is_login_failure(notice: &str) -> bool {
+    notice.contains("Login authentication failed") || notice.contains("Improperly formatted auth")
+}
@@ -0,0 +1,11 @@
+//! Async versions of the connection and client APIs, built on tokio
+//!
+//! These read and write on the runtime instead of needing a thread per connection, and hand
+//! out a `Stream` of events instead of a channel
+#[cfg(feature = "twitch")]
+mod connection;
+#[cfg(feature = "twitch")]
+pub use self::connection::{connect_to, connect_to_twitch, connect_with, Connection, Writer};
+
+mod client;
+pub use self::client::DaemonClient;
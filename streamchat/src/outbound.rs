@@ -0,0 +1,586 @@
+//! Sending messages to Twitch without going over its rate limits
+//!
+//! Twitch allows 20 messages per 30 seconds, or 100 in channels where the user is a moderator,
+//! VIP or the broadcaster. Going over that gets the account muted for a while, so everything
+//! that talks to chat should go through an [`Outbound`](./struct.Outbound.html).
+//!
+//! Messages are queued by [`Priority`](./enum.Priority.html), sent as soon as the limit allows
+//! and each one gets a [`Receipt`](./struct.Receipt.html) with the result
+use crossbeam_channel as channel;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+// twitch drops a message that's the same as the previous one, if it was sent within this
+const DUPLICATE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Something that messages can be written to
+pub trait Sink: Send {
+    /// Sends `data` to `channel`. The channel doesn't have the leading `#`
+    fn send(&mut self, channel: &str, data: &str) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+#[cfg(feature = "twitch")]
+impl Sink for crate::twitch::Writer {
+    fn send(&mut self, channel: &str, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        crate::twitch::Writer::send(self, &format!("#{}", channel), data).map_err(Into::into)
+    }
+}
+
+/// At most `count` messages in any `period`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Limit {
+    pub count: usize,
+    pub period: Duration,
+}
+
+impl Limit {
+    /// The limit for regular users
+    pub const NORMAL: Limit = Limit {
+        count: 20,
+        period: Duration::from_secs(30),
+    };
+
+    /// The limit in channels where the user is a moderator, VIP or the broadcaster
+    pub const ELEVATED: Limit = Limit {
+        count: 100,
+        period: Duration::from_secs(30),
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub limit: Limit,
+    /// Used for the channels marked with
+    /// [`Outbound::set_elevated`](./struct.Outbound.html#method.set_elevated)
+    pub elevated_limit: Limit,
+    /// How many messages can be waiting at once
+    pub queue_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            limit: Limit::NORMAL,
+            elevated_limit: Limit::ELEVATED,
+            queue_size: 32,
+        }
+    }
+}
+
+/// Higher priorities are sent first. Messages with the same priority are sent in order
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendError {
+    /// The queue was full of messages with the same or a higher priority
+    Full,
+    /// The message was the same as the last one to that channel, which Twitch rejects if it
+    /// was sent less than 30 seconds ago
+    Duplicate,
+    /// The sender stopped before the message was sent
+    Closed,
+    /// The sink returned an error
+    Sink(String),
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Full => write!(f, "the queue is full"),
+            SendError::Duplicate => write!(f, "duplicate message"),
+            SendError::Closed => write!(f, "the sender has stopped"),
+            SendError::Sink(err) => write!(f, "cannot send: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// The result of a queued message. Dropping this doesn't cancel the message
+#[derive(Debug)]
+pub struct Receipt(channel::Receiver<Result<(), SendError>>);
+
+impl Receipt {
+    /// Blocks until the message was sent, or failed
+    pub fn wait(self) -> Result<(), SendError> {
+        self.0.recv().unwrap_or(Err(SendError::Closed))
+    }
+
+    /// The result, if there is one yet
+    pub fn try_result(&self) -> Option<Result<(), SendError>> {
+        match self.0.try_recv() {
+            Ok(result) => Some(result),
+            Err(channel::TryRecvError::Empty) => None,
+            Err(channel::TryRecvError::Disconnected) => Some(Err(SendError::Closed)),
+        }
+    }
+}
+
+/// A handle to a rate limited sender, running on its own thread. It is cheap to clone
+///
+/// The thread sends whatever is still queued and stops once every handle is dropped
+#[derive(Debug, Clone)]
+pub struct Outbound {
+    tx: channel::Sender<Request>,
+}
+
+impl Outbound {
+    pub fn start(sink: impl Sink + 'static, config: Config) -> Self {
+        let (tx, rx) = channel::unbounded();
+        let mut worker = Worker::new(sink, config);
+        std::thread::spawn(move || worker.run(rx));
+        Self { tx }
+    }
+
+    /// Queues `data` for `channel` with the normal priority
+    pub fn send(&self, channel: impl AsRef<str>, data: impl ToString) -> Receipt {
+        self.send_with(channel, data, Priority::Normal)
+    }
+
+    pub fn send_with(
+        &self,
+        channel: impl AsRef<str>,
+        data: impl ToString,
+        priority: Priority,
+    ) -> Receipt {
+        let (reply, receipt) = channel::bounded(1);
+        let msg = Pending {
+            channel: crate::normalize_channel(channel.as_ref()),
+            data: data.to_string(),
+            priority,
+            seq: 0,
+            reply,
+        };
+        // if the worker is gone, the receipt reports it as closed
+        let _ = self.tx.send(Request::Send(msg));
+        Receipt(receipt)
+    }
+
+    /// Use the elevated limit for `channel`, for when the user is a moderator, VIP or the
+    /// broadcaster there
+    pub fn set_elevated(&self, channel: impl AsRef<str>, elevated: bool) {
+        let channel = crate::normalize_channel(channel.as_ref());
+        let _ = self.tx.send(Request::Elevated(channel, elevated));
+    }
+}
+
+enum Request {
+    Send(Pending),
+    Elevated(String, bool),
+}
+
+struct Pending {
+    channel: String,
+    data: String,
+    priority: Priority,
+    seq: u64,
+    reply: channel::Sender<Result<(), SendError>>,
+}
+
+impl Pending {
+    fn finish(self, result: Result<(), SendError>) {
+        if let Err(err) = &result {
+            log::warn!("cannot send to #{}: {}", self.channel, err);
+        }
+        let _ = self.reply.send(result);
+    }
+}
+
+/// A token is spent for each message, and comes back `period` after it was spent
+struct Bucket {
+    limit: Limit,
+    spent: std::collections::VecDeque<Instant>,
+}
+
+impl Bucket {
+    fn new(limit: Limit) -> Self {
+        let count = limit.count.max(1);
+        Self {
+            limit: Limit { count, ..limit },
+            spent: Default::default(),
+        }
+    }
+
+    /// When a token is available, `now` if there is one already
+    fn available_at(&mut self, now: Instant) -> Instant {
+        while self
+            .spent
+            .front()
+            .map(|&t| now.duration_since(t) >= self.limit.period)
+            .unwrap_or(false)
+        {
+            self.spent.pop_front();
+        }
+
+        match self.spent.front() {
+            Some(&oldest) if self.spent.len() >= self.limit.count => oldest + self.limit.period,
+            _ => now,
+        }
+    }
+
+    fn take(&mut self, now: Instant) {
+        self.spent.push_back(now);
+    }
+}
+
+struct Worker<S> {
+    sink: S,
+    queue_size: usize,
+    queue: Vec<Pending>,
+    seq: u64,
+    normal: Bucket,
+    elevated: Bucket,
+    elevated_channels: HashSet<String>,
+    // the last message for each channel, and when it was sent. `None` while it is queued
+    last: HashMap<String, (String, Option<Instant>)>,
+}
+
+impl<S: Sink> Worker<S> {
+    fn new(sink: S, config: Config) -> Self {
+        Self {
+            sink,
+            queue_size: config.queue_size.max(1),
+            queue: vec![],
+            seq: 0,
+            normal: Bucket::new(config.limit),
+            elevated: Bucket::new(config.elevated_limit),
+            elevated_channels: HashSet::new(),
+            last: HashMap::new(),
+        }
+    }
+
+    fn run(&mut self, rx: channel::Receiver<Request>) {
+        let mut closed = false;
+        loop {
+            self.flush();
+
+            let wakeup = self.next_wakeup();
+            let req = match (wakeup, closed) {
+                (None, true) => break,
+                (Some(at), true) => {
+                    std::thread::sleep(at.saturating_duration_since(Instant::now()));
+                    continue;
+                }
+                (None, false) => rx.recv().ok(),
+                (Some(at), false) => {
+                    match rx.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                        Ok(req) => Some(req),
+                        Err(channel::RecvTimeoutError::Timeout) => continue,
+                        Err(channel::RecvTimeoutError::Disconnected) => None,
+                    }
+                }
+            };
+
+            match req {
+                Some(req) => self.handle(req),
+                None => closed = true,
+            }
+            while let Ok(req) = rx.try_recv() {
+                self.handle(req)
+            }
+        }
+        log::debug!("outbound sender stopped");
+    }
+
+    fn handle(&mut self, req: Request) {
+        match req {
+            Request::Send(msg) => self.enqueue(msg),
+            Request::Elevated(channel, true) => {
+                self.elevated_channels.insert(channel);
+            }
+            Request::Elevated(channel, false) => {
+                self.elevated_channels.remove(&channel);
+            }
+        }
+    }
+
+    fn enqueue(&mut self, mut msg: Pending) {
+        if let Some((data, sent)) = self.last.get(&msg.channel) {
+            let recent = match sent {
+                Some(sent) => sent.elapsed() < DUPLICATE_WINDOW,
+                None => true,
+            };
+            if *data == msg.data && recent {
+                return msg.finish(Err(SendError::Duplicate));
+            }
+        }
+
+        if self.queue.len() >= self.queue_size {
+            // make room by dropping the newest of the least important messages
+            let (index, lowest) = self
+                .queue
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, p)| (p.priority, std::cmp::Reverse(p.seq)))
+                .map(|(i, p)| (i, p.priority))
+                .expect("queue cannot be empty");
+            if lowest >= msg.priority {
+                return msg.finish(Err(SendError::Full));
+            }
+            let dropped = self.queue.remove(index);
+            self.forget(&dropped);
+            dropped.finish(Err(SendError::Full));
+        }
+
+        self.seq += 1;
+        msg.seq = self.seq;
+        self.last
+            .insert(msg.channel.clone(), (msg.data.clone(), None));
+        self.queue.push(msg);
+    }
+
+    // sends everything that the limits allow, most important first
+    fn flush(&mut self) {
+        loop {
+            let now = Instant::now();
+            let index = {
+                let (normal, elevated) = (&mut self.normal, &mut self.elevated);
+                let channels = &self.elevated_channels;
+                self.queue
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| {
+                        let bucket = if channels.contains(&p.channel) {
+                            &mut *elevated
+                        } else {
+                            &mut *normal
+                        };
+                        bucket.available_at(now) <= now
+                    })
+                    .max_by_key(|(_, p)| (p.priority, std::cmp::Reverse(p.seq)))
+                    .map(|(i, _)| i)
+            };
+
+            let msg = match index {
+                Some(index) => self.queue.remove(index),
+                None => return,
+            };
+
+            self.bucket(&msg.channel).take(now);
+            let result = self
+                .sink
+                .send(&msg.channel, &msg.data)
+                .map_err(|err| SendError::Sink(err.to_string()));
+            match result {
+                Ok(..) => {
+                    if let Some((data, sent)) = self.last.get_mut(&msg.channel) {
+                        if *data == msg.data {
+                            *sent = Some(now);
+                        }
+                    }
+                }
+                Err(..) => self.forget(&msg),
+            }
+            msg.finish(result);
+        }
+    }
+
+    // the earliest time something in the queue could be sent
+    fn next_wakeup(&mut self) -> Option<Instant> {
+        let now = Instant::now();
+        let (normal, elevated) = (&mut self.normal, &mut self.elevated);
+        let channels = &self.elevated_channels;
+        self.queue
+            .iter()
+            .map(|p| {
+                if channels.contains(&p.channel) {
+                    elevated.available_at(now)
+                } else {
+                    normal.available_at(now)
+                }
+            })
+            .min()
+    }
+
+    fn bucket(&mut self, channel: &str) -> &mut Bucket {
+        if self.elevated_channels.contains(channel) {
+            &mut self.elevated
+        } else {
+            &mut self.normal
+        }
+    }
+
+    // an unsent message shouldn't stop the same text from being sent again
+    fn forget(&mut self, msg: &Pending) {
+        if self.last.get(&msg.channel).map(|(data, _)| data) == Some(&msg.data) {
+            self.last.remove(&msg.channel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        sent: Vec<(String, String)>,
+        fail: bool,
+    }
+
+    impl Sink for Recorder {
+        fn send(&mut self, channel: &str, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+            if self.fail {
+                return Err("broken".into());
+            }
+            self.sent.push((channel.to_string(), data.to_string()));
+            Ok(())
+        }
+    }
+
+    fn worker(count: usize, queue_size: usize) -> Worker<Recorder> {
+        let limit = Limit {
+            count,
+            period: Duration::from_secs(60),
+        };
+        let config = Config {
+            limit,
+            elevated_limit: Limit {
+                count: 100,
+                ..limit
+            },
+            queue_size,
+        };
+        Worker::new(Recorder::default(), config)
+    }
+
+    fn send(
+        worker: &mut Worker<Recorder>,
+        channel: &str,
+        data: &str,
+        priority: Priority,
+    ) -> Receipt {
+        let (reply, receipt) = channel::bounded(1);
+        worker.handle(Request::Send(Pending {
+            channel: channel.to_string(),
+            data: data.to_string(),
+            priority,
+            seq: 0,
+            reply,
+        }));
+        Receipt(receipt)
+    }
+
+    fn sent(worker: &Worker<Recorder>) -> Vec<&str> {
+        worker
+            .sink
+            .sent
+            .iter()
+            .map(|(_, data)| data.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn bucket() {
+        let limit = Limit {
+            count: 2,
+            period: Duration::from_secs(30),
+        };
+        let mut bucket = Bucket::new(limit);
+        let start = Instant::now();
+        assert_eq!(bucket.available_at(start), start);
+        bucket.take(start);
+        bucket.take(start + Duration::from_secs(10));
+        assert_eq!(
+            bucket.available_at(start + Duration::from_secs(20)),
+            start + Duration::from_secs(30)
+        );
+        // the first token came back
+        let later = start + Duration::from_secs(30);
+        assert_eq!(bucket.available_at(later), later);
+    }
+
+    #[test]
+    fn rate_limited() {
+        let mut worker = worker(2, 8);
+        let receipts = (0..3)
+            .map(|i| send(&mut worker, "museun", &i.to_string(), Priority::Normal))
+            .collect::<Vec<_>>();
+        worker.flush();
+
+        assert_eq!(sent(&worker), vec!["0", "1"]);
+        assert_eq!(receipts[0].try_result(), Some(Ok(())));
+        assert_eq!(receipts[2].try_result(), None);
+        let wakeup = worker.next_wakeup().unwrap();
+        assert!(wakeup > Instant::now() + Duration::from_secs(50));
+
+        // the elevated limit is separate
+        worker.handle(Request::Elevated("other".into(), true));
+        send(&mut worker, "other", "3", Priority::Normal);
+        worker.flush();
+        assert_eq!(sent(&worker), vec!["0", "1", "3"]);
+    }
+
+    #[test]
+    fn most_important_first() {
+        let mut worker = worker(1, 8);
+        send(&mut worker, "museun", "low", Priority::Low);
+        send(&mut worker, "museun", "normal", Priority::Normal);
+        send(&mut worker, "museun", "high", Priority::High);
+        worker.flush();
+        assert_eq!(sent(&worker), vec!["high"]);
+    }
+
+    #[test]
+    fn full_queue_drops_the_least_important() {
+        let mut worker = worker(1, 2);
+        let low = send(&mut worker, "museun", "low", Priority::Low);
+        send(&mut worker, "museun", "normal", Priority::Normal);
+        send(&mut worker, "museun", "high", Priority::High);
+        assert_eq!(low.try_result(), Some(Err(SendError::Full)));
+
+        let another = send(&mut worker, "museun", "another", Priority::Normal);
+        assert_eq!(another.try_result(), Some(Err(SendError::Full)));
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let mut worker = worker(10, 8);
+        send(&mut worker, "museun", "hello", Priority::Normal);
+        let queued = send(&mut worker, "museun", "hello", Priority::Normal);
+        assert_eq!(queued.try_result(), Some(Err(SendError::Duplicate)));
+
+        worker.flush();
+        let again = send(&mut worker, "museun", "hello", Priority::Normal);
+        assert_eq!(again.try_result(), Some(Err(SendError::Duplicate)));
+
+        // another channel, or something in between, is fine
+        send(&mut worker, "other", "hello", Priority::Normal);
+        send(&mut worker, "museun", "hi", Priority::Normal);
+        send(&mut worker, "museun", "hello", Priority::Normal);
+        worker.flush();
+        assert_eq!(sent(&worker), vec!["hello", "hello", "hi", "hello"]);
+    }
+
+    #[test]
+    fn failed_messages_can_be_sent_again() {
+        let mut worker = worker(10, 8);
+        worker.sink.fail = true;
+        let failed = send(&mut worker, "museun", "hello", Priority::Normal);
+        worker.flush();
+        assert_eq!(
+            failed.try_result(),
+            Some(Err(SendError::Sink("broken".into())))
+        );
+
+        worker.sink.fail = false;
+        let retry = send(&mut worker, "museun", "hello", Priority::Normal);
+        worker.flush();
+        assert_eq!(retry.try_result(), Some(Ok(())));
+    }
+
+    #[test]
+    fn queued_messages_are_sent_after_the_handles_are_dropped() {
+        let sink = Recorder::default();
+        let outbound = Outbound::start(sink, Config::default());
+        let receipt = outbound.send("#museun", "hello");
+        drop(outbound);
+        assert_eq!(receipt.wait(), Ok(()));
+    }
+}
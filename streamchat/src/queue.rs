@@ -1,9 +1,28 @@
 #![allow(dead_code)]
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
+/// A bounded queue that numbers what is pushed into it
+///
+/// Every element gets a sequence id, one higher than the last one. The ids are never reused,
+/// so [`since`](#method.since) can find what a reader hasn't seen yet even after the oldest
+/// elements were evicted. Lookups by id are binary searches.
+///
+/// Elements are evicted when there are more than `size` of them, and, if a
+/// [`max_age`](#method.max_age) is set, once they're older than that. Expired elements are
+/// skipped by the lookups even if nothing was pushed since
 pub struct Queue<T> {
-    data: VecDeque<T>,
+    data: VecDeque<Entry<T>>,
     size: usize,
+    max_age: Option<Duration>,
+    seq: u64,
+    evicted: u64,
+}
+
+struct Entry<T> {
+    seq: u64,
+    added: Instant,
+    item: T,
 }
 
 impl<T> Queue<T> {
@@ -11,22 +30,93 @@ impl<T> Queue<T> {
         Queue {
             data: VecDeque::with_capacity(size),
             size,
+            max_age: None,
+            seq: 0,
+            evicted: 0,
         }
     }
 
+    /// Also evict elements once they are older than `max_age`
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Changes how old elements can get, `None` keeps them until they're pushed out
+    pub fn set_max_age(&mut self, max_age: impl Into<Option<Duration>>) {
+        self.max_age = max_age.into();
+        self.expire();
+    }
+
+    /// Pushes `element`, returning the oldest one if the queue was full. Its sequence id is
+    /// [`last_seq`](#method.last_seq) afterwards
     pub fn push(&mut self, element: T) -> Option<T> {
-        let out = if self.data.len() == self.size {
-            self.data.pop_front()
-        } else {
-            None
-        };
+        self.insert(self.seq + 1, element).1
+    }
 
-        self.data.push_back(element);
-        out
+    /// Pushes `element` with a sequence id from somewhere else, e.g. the daemon. Ids don't have
+    /// to be contiguous, but one that isn't higher than the last is bumped to the next one.
+    /// Returns the id that was used
+    pub fn push_with_seq(&mut self, seq: u64, element: T) -> u64 {
+        self.insert(seq, element).0
+    }
+
+    fn insert(&mut self, seq: u64, element: T) -> (u64, Option<T>) {
+        self.expire();
+        self.seq = seq.max(self.seq + 1);
+        if self.size == 0 {
+            self.evicted = self.seq;
+            return (self.seq, Some(element));
+        }
+
+        let mut out = None;
+        while self.data.len() >= self.size {
+            out = self.evict();
+        }
+        self.data.push_back(Entry {
+            seq: self.seq,
+            added: Instant::now(),
+            item: element,
+        });
+        (self.seq, out)
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        self.data.pop_front()
+        self.expire();
+        self.evict()
+    }
+
+    // removes the oldest element, remembering its id
+    fn evict(&mut self) -> Option<T> {
+        let entry = self.data.pop_front()?;
+        self.evicted = self.evicted.max(entry.seq);
+        Some(entry.item)
+    }
+
+    /// Drops the elements older than the max age
+    pub fn expire(&mut self) {
+        for _ in 0..self.expired() {
+            self.evict();
+        }
+    }
+
+    // how many of the oldest elements are past the max age. they are pushed in order, so
+    // these are at the front
+    fn expired(&self) -> usize {
+        match self.max_age {
+            Some(max_age) => self
+                .data
+                .partition_point(|entry| entry.added.elapsed() >= max_age),
+            None => 0,
+        }
+    }
+
+    /// Changes the maximum number of elements, evicting the oldest ones when shrinking
+    pub fn resize(&mut self, size: usize) {
+        self.size = size;
+        while self.data.len() > size {
+            self.evict();
+        }
     }
 
     pub fn size(&self) -> usize {
@@ -38,20 +128,194 @@ impl<T> Queue<T> {
     }
 
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.data.len() - self.expired()
+    }
+
+    /// The sequence id of the last pushed element, or 0 if nothing was pushed yet
+    pub fn last_seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// The sequence id of the newest element that was dropped by the size, the max age,
+    /// [`pop`](#method.pop), [`clear`](#method.clear) or [`retain`](#method.retain), or 0 if none
+    /// were. A reader that has only seen up to an id lower than this has missed something
+    pub fn evicted_seq(&self) -> u64 {
+        match self.expired() {
+            0 => self.evicted,
+            expired => self.evicted.max(self.data[expired - 1].seq),
+        }
+    }
+
+    /// The sequence id of the oldest element still in the queue
+    pub fn first_seq(&self) -> Option<u64> {
+        self.data.get(self.expired()).map(|entry| entry.seq)
+    }
+
+    pub fn get(&self, seq: u64) -> Option<&T> {
+        self.data
+            .binary_search_by_key(&seq, |entry| entry.seq)
+            .ok()
+            .filter(|&index| index >= self.expired())
+            .map(|index| &self.data[index].item)
+    }
+
+    /// Everything pushed after `seq`, oldest first. `since(0)` is everything
+    pub fn since(&self, seq: u64) -> impl Iterator<Item = (u64, &T)> {
+        let start = self.data.partition_point(|entry| entry.seq <= seq);
+        self.data
+            .range(start.max(self.expired())..)
+            .map(|entry| (entry.seq, &entry.item))
+    }
+
+    /// Up to `count` elements pushed before `seq`, oldest first. This is for paging back
+    /// through the history
+    pub fn before(&self, seq: u64, count: usize) -> impl Iterator<Item = (u64, &T)> {
+        let end = self.data.partition_point(|entry| entry.seq < seq);
+        let start = end.saturating_sub(count).max(self.expired()).min(end);
+        self.data
+            .range(start..end)
+            .map(|entry| (entry.seq, &entry.item))
     }
 
     pub fn clear(&mut self) {
+        if let Some(entry) = self.data.back() {
+            self.evicted = self.evicted.max(entry.seq);
+        }
         self.data.clear();
     }
 
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        let evicted = &mut self.evicted;
+        self.data.retain(|entry| {
+            let keep = f(&entry.item);
+            if !keep {
+                *evicted = (*evicted).max(entry.seq);
+            }
+            keep
+        })
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &T> {
-        self.data.iter()
+        self.data.range(self.expired()..).map(|entry| &entry.item)
     }
 }
 
 impl<T: std::fmt::Debug> std::fmt::Debug for Queue<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Queue").field("size", &self.size).finish()
+        f.debug_struct("Queue")
+            .field("size", &self.size)
+            .field("max_age", &self.max_age)
+            .field("last_seq", &self.seq)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seqs<'a>(iter: impl Iterator<Item = (u64, &'a u64)>) -> Vec<u64> {
+        iter.map(|(seq, _)| seq).collect()
+    }
+
+    #[test]
+    fn push_returns_the_evicted_element() {
+        let mut queue = Queue::new(2);
+        assert_eq!(queue.push(1), None);
+        assert_eq!(queue.push(2), None);
+        assert_eq!(queue.push(3), Some(1));
+        assert_eq!(queue.last_seq(), 3);
+        assert_eq!(queue.evicted_seq(), 1);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn push_with_seq_only_goes_up() {
+        let mut queue = Queue::new(4);
+        assert_eq!(queue.push_with_seq(10, 0), 10);
+        assert_eq!(queue.push_with_seq(5, 0), 11);
+        assert_eq!(queue.push_with_seq(20, 0), 20);
+        assert_eq!(queue.get(11), Some(&0));
+        assert_eq!(queue.get(12), None);
+    }
+
+    #[test]
+    fn since_and_before() {
+        let mut queue = Queue::new(5);
+        for i in 1..=8 {
+            queue.push_with_seq(i * 10, i);
+        }
+        // 10, 20 and 30 were evicted
+        assert_eq!(queue.first_seq(), Some(40));
+        assert_eq!(queue.evicted_seq(), 30);
+
+        assert_eq!(seqs(queue.since(0)), vec![40, 50, 60, 70, 80]);
+        assert_eq!(seqs(queue.since(55)), vec![60, 70, 80]);
+        assert_eq!(seqs(queue.since(80)), Vec::<u64>::new());
+
+        assert_eq!(seqs(queue.before(70, 2)), vec![50, 60]);
+        assert_eq!(seqs(queue.before(45, 10)), vec![40]);
+        assert_eq!(seqs(queue.before(40, 10)), Vec::<u64>::new());
+        assert_eq!(seqs(queue.before(u64::MAX, 3)), vec![60, 70, 80]);
+    }
+
+    #[test]
+    fn resize_evicts_the_oldest() {
+        let mut queue = Queue::new(4);
+        for i in 1..=4 {
+            queue.push(i);
+        }
+        queue.resize(2);
+        assert_eq!(queue.size(), 2);
+        assert_eq!(seqs(queue.since(0)), vec![3, 4]);
+        assert_eq!(queue.evicted_seq(), 2);
+
+        queue.resize(3);
+        queue.push(5);
+        assert_eq!(seqs(queue.since(0)), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn clear_and_retain_count_as_evicted() {
+        let mut queue = Queue::new(8);
+        for i in 1..=4 {
+            queue.push(i);
+        }
+        queue.retain(|&i| i != 3);
+        assert_eq!(queue.evicted_seq(), 3);
+        assert_eq!(seqs(queue.since(0)), vec![1, 2, 4]);
+
+        queue.clear();
+        assert!(queue.is_empty());
+        assert_eq!(queue.evicted_seq(), 4);
+        assert_eq!(queue.push_with_seq(0, 5), 5);
+    }
+
+    #[test]
+    fn expired_elements_are_skipped_without_a_push() {
+        let mut queue = Queue::new(8).max_age(Duration::from_millis(50));
+        queue.push(1);
+        queue.push(2);
+        std::thread::sleep(Duration::from_millis(80));
+        queue.push_with_seq(0, 3);
+        // the new one isn't expired, but the others are gone
+        assert_eq!(seqs(queue.since(0)), vec![3]);
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(queue.is_empty());
+        assert_eq!(queue.first_seq(), None);
+        assert_eq!(queue.get(3), None);
+        assert_eq!(queue.evicted_seq(), 3);
+        assert_eq!(seqs(queue.since(0)), Vec::<u64>::new());
+        assert_eq!(seqs(queue.before(10, 10)), Vec::<u64>::new());
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn zero_size_keeps_nothing() {
+        let mut queue = Queue::new(0);
+        assert_eq!(queue.push(1), Some(1));
+        assert_eq!(queue.evicted_seq(), 1);
+        assert!(queue.is_empty());
     }
 }
@@ -0,0 +1,321 @@
+//! Splitting a message into text, emotes, mentions, urls and cheers
+//!
+//! Twitch gives emote ranges as (inclusive) codepoint indices, these are converted to byte
+//! offsets here so the spans can be sliced out of the `String` directly
+//!
+//! Urls are the words starting with `http://`, `https://` or `www.`. Bare domains like
+//! `example.com` are left as text, as they can't be told apart from a missing space
+use serde::Serialize;
+
+// the cheermotes every channel has. channels can add their own, see `parse`
+const CHEER_PREFIXES: &[&str] = &[
+    "Cheer",
+    "DoodleCheer",
+    "BibleThump",
+    "cheerwhal",
+    "Corgo",
+    "uni",
+    "ShowLove",
+    "Party",
+    "SeemsGood",
+    "Pride",
+    "Kappa",
+    "FrankerZ",
+    "HeyGuys",
+    "DansGame",
+    "EleGiggle",
+    "TriHard",
+    "Kreygasm",
+    "4Head",
+    "SwiftRage",
+    "NotLikeThis",
+    "FailFish",
+    "VoHiYo",
+    "PJSalt",
+    "MrDestructoid",
+    "bday",
+    "RIPCheer",
+    "Shamrock",
+    "BitBoss",
+    "Streamlabs",
+    "Muxy",
+    "HolidayCheer",
+    "Goal",
+    "Anon",
+    "Charity",
+];
+
+// which words can be cheers
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Cheers {
+    None,
+    // only the global cheermotes
+    Known,
+    // any word and a number
+    All,
+}
+
+/// A piece of a message
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "data", rename_all = "snake_case")]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Emote {
+        id: &'a str,
+        name: &'a str,
+    },
+    /// The name, without the leading `@`
+    Mention(&'a str),
+    Url(&'a str),
+    Cheer {
+        prefix: &'a str,
+        amount: u64,
+    },
+}
+
+/// Splits `data` into segments.
+///
+/// `emotes` are `(id, start, end)` with `start` and `end` being inclusive codepoint indices.
+/// Overlapping or out of bounds ranges are ignored.
+///
+/// Cheers are only looked for if `bits` were attached. Words like `Cheer100` with one of the
+/// global prefixes are cheers. Channels can have their own prefixes, so if every word followed
+/// by a number adds up to `bits`, those are all cheers instead. That way `GG2` or `Team10` is
+/// only a cheer when it has to be
+pub fn parse<'a>(
+    data: &'a str,
+    emotes: impl IntoIterator<Item = (&'a str, usize, usize)>,
+    bits: Option<u64>,
+) -> Vec<Segment<'a>> {
+    let offsets = data
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(data.len()))
+        .collect::<Vec<_>>();
+
+    let mut emotes = emotes
+        .into_iter()
+        .filter(|&(_, start, end)| start <= end && end + 1 < offsets.len())
+        .map(|(id, start, end)| (id, offsets[start], offsets[end + 1]))
+        .collect::<Vec<_>>();
+    emotes.sort_by_key(|&(_, start, _)| start);
+
+    let bits = match bits {
+        Some(bits) => bits,
+        None => return split(data, &emotes, Cheers::None),
+    };
+    let out = split(data, &emotes, Cheers::All);
+    let cheered = out.iter().fold(0u64, |sum, segment| match segment {
+        Segment::Cheer { amount, .. } => sum.saturating_add(*amount),
+        _ => sum,
+    });
+    if cheered == bits {
+        return out;
+    }
+    split(data, &emotes, Cheers::Known)
+}
+
+// `emotes` are byte ranges, sorted by where they start
+fn split<'a>(
+    data: &'a str,
+    emotes: &[(&'a str, usize, usize)],
+    cheers: Cheers,
+) -> Vec<Segment<'a>> {
+    let mut out = vec![];
+    let mut pos = 0;
+    for &(id, start, end) in emotes {
+        if start < pos {
+            continue;
+        }
+        words(data, pos, start, cheers, &mut out);
+        out.push(Segment::Emote {
+            id,
+            name: &data[start..end],
+        });
+        pos = end;
+    }
+    words(data, pos, data.len(), cheers, &mut out);
+    out
+}
+
+// classifies the words in data[start..end], merging adjacent plain text
+fn words<'a>(data: &'a str, start: usize, end: usize, cheers: Cheers, out: &mut Vec<Segment<'a>>) {
+    let slice = &data[start..end];
+    let mut text = start;
+
+    for word in slice.split_whitespace() {
+        let word_start = start + (word.as_ptr() as usize - slice.as_ptr() as usize);
+        let (segment, len) = match classify(word, cheers) {
+            Some(segment) => segment,
+            None => continue,
+        };
+
+        if text < word_start {
+            out.push(Segment::Text(&data[text..word_start]));
+        }
+        out.push(segment);
+        text = word_start + len;
+    }
+
+    if text < end {
+        out.push(Segment::Text(&data[text..end]));
+    }
+}
+
+// returns the segment and how many bytes of the word it used
+fn classify(word: &str, cheers: Cheers) -> Option<(Segment<'_>, usize)> {
+    if let Some(name) = word.strip_prefix('@') {
+        let len = name
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(name.len());
+        if len == 0 {
+            return None;
+        }
+        return Some((Segment::Mention(&name[..len]), len + 1));
+    }
+
+    if ["http://", "https://", "www."]
+        .iter()
+        .any(|scheme| word.starts_with(scheme))
+    {
+        let url = word.trim_end_matches(|c| ".,!?:;)'\"".contains(c));
+        return Some((Segment::Url(url), url.len()));
+    }
+
+    if cheers != Cheers::None {
+        let digits = word
+            .rfind(|c: char| !c.is_ascii_digit())
+            .map_or(0, |i| i + 1);
+        let (prefix, amount) = word.split_at(digits);
+        let known = || {
+            CHEER_PREFIXES
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(prefix))
+        };
+        if !prefix.is_empty()
+            && !amount.is_empty()
+            && prefix.chars().all(|c| c.is_ascii_alphanumeric())
+            && (cheers == Cheers::All || known())
+        {
+            let amount = amount.parse().ok()?;
+            return Some((Segment::Cheer { prefix, amount }, word.len()));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Segment::*;
+
+    #[test]
+    fn emote_ranges_are_codepoints() {
+        // the emote ranges count codepoints, the names are sliced by bytes
+        let data = "héllo Kappa ☃ Kappa!";
+        let emotes = vec![("25", 6, 10), ("25", 14, 18)];
+        assert_eq!(
+            parse(data, emotes, None),
+            vec![
+                Text("héllo "),
+                Emote {
+                    id: "25",
+                    name: "Kappa"
+                },
+                Text(" ☃ "),
+                Emote {
+                    id: "25",
+                    name: "Kappa"
+                },
+                Text("!"),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_emote_ranges_are_ignored() {
+        let data = "Kappa Keepo";
+        // past the end, backwards, and overlapping the first one
+        let emotes = vec![("25", 0, 4), ("1", 6, 11), ("2", 4, 2), ("3", 2, 7)];
+        assert_eq!(
+            parse(data, emotes, None),
+            vec![
+                Emote {
+                    id: "25",
+                    name: "Kappa"
+                },
+                Text(" Keepo"),
+            ]
+        );
+    }
+
+    #[test]
+    fn mentions_and_urls() {
+        assert_eq!(
+            parse(
+                "@foo_1, see https://example.com/a?b=c. or www.example.com",
+                vec![],
+                None
+            ),
+            vec![
+                Mention("foo_1"),
+                Text(", see "),
+                Url("https://example.com/a?b=c"),
+                Text(". or "),
+                Url("www.example.com"),
+            ]
+        );
+        assert_eq!(parse("@ me", vec![], None), vec![Text("@ me")]);
+        assert_eq!(
+            parse("example.com", vec![], None),
+            vec![Text("example.com")]
+        );
+    }
+
+    #[test]
+    fn cheers_need_bits() {
+        assert_eq!(parse("Cheer100", vec![], None), vec![Text("Cheer100")]);
+        assert_eq!(
+            parse("Cheer100 gg", vec![], Some(100)),
+            vec![
+                Cheer {
+                    prefix: "Cheer",
+                    amount: 100
+                },
+                Text(" gg"),
+            ]
+        );
+    }
+
+    #[test]
+    fn cheers_with_unknown_prefixes_have_to_add_up() {
+        // GG2 and Team10 would make it 112
+        assert_eq!(
+            parse("GG2 Team10 Cheer100", vec![], Some(100)),
+            vec![
+                Text("GG2 Team10 "),
+                Cheer {
+                    prefix: "Cheer",
+                    amount: 100
+                },
+            ]
+        );
+        // a channel's own cheermotes
+        assert_eq!(
+            parse("museun50 kappa50", vec![], Some(100)),
+            vec![
+                Cheer {
+                    prefix: "museun",
+                    amount: 50
+                },
+                Text(" "),
+                Cheer {
+                    prefix: "kappa",
+                    amount: 50
+                },
+            ]
+        );
+        assert_eq!(parse("Team10", vec![], Some(5)), vec![Text("Team10")]);
+    }
+}
@@ -0,0 +1,36 @@
+//! [JSON Schemas](https://json-schema.org) for what `streamchatd` sends, generated from the types
+//!
+//! Each line from the daemon is an [`Event`](../enum.Event.html), with an added `seq` field for
+//! its sequence id. Clients that don't send a [hello](../handshake/index.html) only get chat
+//! messages, in the shape version 1 of the protocol used, see [`v1`](./fn.v1.html)
+use crate::encoding::Sequenced;
+use crate::handshake::{Hello, Reply};
+use crate::message::V1;
+use crate::Message;
+use schemars::schema::RootSchema;
+
+/// The schema for a line from the daemon, an event with its `seq`
+pub fn event() -> RootSchema {
+    schemars::schema_for!(Sequenced<'static>)
+}
+
+/// The schema for a chat message, without the `event` and `seq` fields
+pub fn message() -> RootSchema {
+    schemars::schema_for!(Message)
+}
+
+/// The schema for a line sent to clients that don't send a hello. This is a version 1 message:
+/// the timestamp is a string, colors and badges use twitchchat's names and emote ids are numbers
+pub fn v1() -> RootSchema {
+    schemars::schema_for!(V1<'static>)
+}
+
+/// The schema for the [hello](../handshake/struct.Hello.html) a client can send
+pub fn hello() -> RootSchema {
+    schemars::schema_for!(Hello)
+}
+
+/// The schema for the daemon's [reply](../handshake/enum.Reply.html) to a hello
+pub fn reply() -> RootSchema {
+    schemars::schema_for!(Reply)
+}
@@ -0,0 +1,222 @@
+use crate::types::{Badge, Color, Emote, Range, Tags};
+use crate::{Message, Reply, Version};
+
+/// Builds a [`Message`](../struct.Message.html) and the tags Twitch would have sent with it.
+///
+/// The tags are made from the fields, e.g. `.emote("25", "Kappa")` on `"hello Kappa"` sets the
+/// emote range and `emotes=25:6-10`. Tags set with [`tag`](#method.tag) win over those
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    msg: Message,
+    // tags set explicitly, these win over the ones derived from the fields
+    tags: Vec<(String, String)>,
+}
+
+impl MessageBuilder {
+    /// A message from `name` with `data`, to the `#streamchat` channel
+    pub fn new(name: impl ToString, data: impl ToString) -> Self {
+        let name = name.to_string();
+        Self {
+            msg: Message {
+                version: Version::CURRENT,
+                userid: user_id(&name).to_string(),
+                timestamp: crate::make_timestamp(),
+                channel: "streamchat".to_string(),
+                id: None,
+                room_id: None,
+                name,
+                data: data.to_string(),
+                color: Color::default(),
+                custom_color: None,
+                is_action: false,
+                badges: vec![],
+                emotes: vec![],
+                reply_to: None,
+                bits: None,
+                first_msg: false,
+                tags: Tags::default(),
+            },
+            tags: vec![],
+        }
+    }
+
+    pub fn channel(mut self, channel: impl AsRef<str>) -> Self {
+        self.msg.channel = crate::normalize_channel(channel.as_ref());
+        self
+    }
+
+    /// Defaults to a number derived from the name
+    pub fn userid(mut self, userid: impl ToString) -> Self {
+        self.msg.userid = userid.to_string();
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.msg.timestamp = timestamp;
+        self
+    }
+
+    pub fn id(mut self, id: impl ToString) -> Self {
+        self.msg.id = Some(id.to_string());
+        self
+    }
+
+    pub fn room_id(mut self, room_id: u64) -> Self {
+        self.msg.room_id = Some(room_id);
+        self
+    }
+
+    pub fn color(mut self, color: impl Into<Color>) -> Self {
+        self.msg.color = color.into();
+        self
+    }
+
+    pub fn custom_color(mut self, color: impl Into<Color>) -> Self {
+        self.msg.custom_color = Some(color.into());
+        self
+    }
+
+    /// Makes this a `/me` message
+    pub fn action(mut self) -> Self {
+        self.msg.is_action = true;
+        self
+    }
+
+    pub fn badge(mut self, kind: impl ToString, version: impl ToString) -> Self {
+        self.msg.badges.push(Badge {
+            kind: kind.to_string(),
+            version: version.to_string(),
+        });
+        self
+    }
+
+    /// Adds a subscriber badge, and the `badge-info` with the months
+    pub fn subscriber(self, months: u64) -> Self {
+        // twitch's badges are for tiers of months
+        let tier = [0, 3, 6, 9, 12, 24, 36, 48, 60]
+            .iter()
+            .rev()
+            .find(|&&tier| tier <= months)
+            .copied()
+            .unwrap_or_default();
+        self.badge("subscriber", tier)
+            .tag("badge-info", format!("subscriber/{}", months))
+    }
+
+    /// Marks every whole word `name` in the data as the emote `id`
+    pub fn emote(self, id: impl ToString, name: &str) -> Self {
+        let mut pos = 0;
+        let mut ranges = vec![];
+        for word in self.msg.data.split(' ') {
+            let len = word.chars().count();
+            if word == name && len > 0 {
+                ranges.push((pos, pos + len - 1));
+            }
+            pos += len + 1;
+        }
+        ranges.into_iter().fold(self, |this, (start, end)| {
+            this.emote_range(id.to_string(), start, end)
+        })
+    }
+
+    /// Adds an emote at the inclusive codepoint range `start..=end`
+    pub fn emote_range(mut self, id: impl ToString, start: usize, end: usize) -> Self {
+        let id = id.to_string();
+        let range = Range { start, end };
+        match self.msg.emotes.iter_mut().find(|emote| emote.id == id) {
+            Some(emote) => emote.ranges.push(range),
+            None => self.msg.emotes.push(Emote {
+                id,
+                ranges: vec![range],
+            }),
+        }
+        self
+    }
+
+    pub fn bits(mut self, bits: u64) -> Self {
+        self.msg.bits = Some(bits);
+        self
+    }
+
+    pub fn first_msg(mut self) -> Self {
+        self.msg.first_msg = true;
+        self
+    }
+
+    pub fn reply_to(mut self, parent: &Message) -> Self {
+        self.msg.reply_to = Some(Reply {
+            id: parent.id.clone().unwrap_or_default(),
+            userid: parent.userid.clone(),
+            name: parent.name.clone(),
+            data: parent.data.clone(),
+        });
+        self
+    }
+
+    /// Sets a tag, overriding the one made from the fields
+    pub fn tag(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.tags.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn build(self) -> Message {
+        let Self { mut msg, tags } = self;
+
+        let mut map = std::collections::HashMap::new();
+        let mut set = |key: &str, value: String| {
+            map.insert(key.to_string(), value);
+        };
+
+        set("user-id", msg.userid.clone());
+        set("display-name", msg.name.clone());
+        set("color", msg.color.rgb.to_string());
+        set("tmi-sent-ts", msg.timestamp.to_string());
+        set(
+            "badges",
+            join(&msg.badges, ",", |b| format!("{}/{}", b.kind, b.version)),
+        );
+        set("emotes", join(&msg.emotes, "/", format_emote));
+        set("first-msg", (msg.first_msg as u8).to_string());
+        set(
+            "mod",
+            (msg.badges.iter().any(|b| b.kind == "moderator") as u8).to_string(),
+        );
+        if let Some(id) = &msg.id {
+            set("id", id.clone());
+        }
+        if let Some(room_id) = msg.room_id {
+            set("room-id", room_id.to_string());
+        }
+        if let Some(bits) = msg.bits {
+            set("bits", bits.to_string());
+        }
+        if let Some(reply) = &msg.reply_to {
+            set("reply-parent-msg-id", reply.id.clone());
+            set("reply-parent-user-id", reply.userid.clone());
+            set("reply-parent-display-name", reply.name.clone());
+            set("reply-parent-msg-body", reply.data.clone());
+        }
+        for (key, value) in tags {
+            set(&key, value);
+        }
+
+        msg.tags = Tags(map);
+        msg
+    }
+}
+
+fn format_emote(emote: &Emote) -> String {
+    let ranges = join(&emote.ranges, ",", |r| format!("{}-{}", r.start, r.end));
+    format!("{}:{}", emote.id, ranges)
+}
+
+fn join<T>(items: &[T], sep: &str, f: impl Fn(&T) -> String) -> String {
+    items.iter().map(f).collect::<Vec<_>>().join(sep)
+}
+
+// a stable id, so the same name always gets the same one
+pub(super) fn user_id(name: &str) -> u64 {
+    name.bytes().fold(0xCBF2_9CE4_8422_2325, |hash: u64, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100_0000_01B3)
+    }) % 1_000_000_000
+}
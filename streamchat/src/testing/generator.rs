@@ -0,0 +1,377 @@
+use super::builder::{user_id, MessageBuilder};
+use crate::event::{Raid, SubKind, Subscription, Timeout};
+use crate::rng::Rng;
+use crate::types::{Color, Rgb, NAMED_COLORS};
+use crate::{Event, Message, Tags};
+
+const NAMES: &[&str] = &[
+    "museun",
+    "shaken_bot",
+    "coffee_bean",
+    "xXsniperXx",
+    "lurker42",
+    "night_owl",
+    "pixelpusher",
+    "cheesy_nachos",
+    "Mr_Flappy",
+    "aRandomUser",
+    "s1lentbob",
+    "tomato_soup",
+    "KeyboardCat",
+    "ferris_fan",
+    "gg_no_re",
+    "BackseatGamer",
+    "quietmouse",
+    "zzz_sleepy",
+    "the_real_bob",
+    "ümlautFan",
+    "トマト",
+    "김치찌개",
+    "Ёжик",
+    "小龙",
+    "ñandú_rider",
+    "Ωmega",
+];
+
+const WORDS: &[&str] = &[
+    "hello",
+    "hi",
+    "chat",
+    "lol",
+    "gg",
+    "wp",
+    "what",
+    "is",
+    "this",
+    "that",
+    "game",
+    "stream",
+    "nice",
+    "play",
+    "clip",
+    "it",
+    "the",
+    "a",
+    "no",
+    "yes",
+    "wait",
+    "why",
+    "how",
+    "did",
+    "you",
+    "see",
+    "rust",
+    "compile",
+    "error",
+    "borrow",
+    "checker",
+    "again",
+    "first",
+    "time",
+    "here",
+    "love",
+    "music",
+    "volume",
+    "pls",
+    "F",
+    "W",
+    "L",
+    "héllo",
+    "café",
+    "日本語",
+    "ありがとう",
+    "좋아요",
+    "привет",
+    "😂",
+    "🔥",
+    "👀",
+    "❤️",
+    "🦀",
+    "¯\\_(ツ)_/¯",
+];
+
+const EMOTES: &[(&str, &str)] = &[
+    ("25", "Kappa"),
+    ("88", "PogChamp"),
+    ("354", "4Head"),
+    ("41", "Kreygasm"),
+    ("86", "BibleThump"),
+    ("1902", "Keepo"),
+    ("425618", "LUL"),
+    ("81274", "VoHiYo"),
+];
+
+#[derive(Debug, Clone)]
+struct User {
+    name: String,
+    color: Color,
+    badges: Vec<(&'static str, String)>,
+    sub_months: Option<u64>,
+}
+
+/// Makes up realistic chat from a seed. The same seed always makes the same chat
+///
+/// The users have unicode names, colors, badges and subscriptions. Their messages have emotes
+/// with valid ranges, actions, mentions, replies, cheers and the occasional very long line
+#[derive(Debug, Clone)]
+pub struct Generator {
+    rng: Rng,
+    channels: Vec<String>,
+    users: Vec<User>,
+    timestamp: u64,
+    // for replies, deletes and timeouts
+    recent: Vec<Message>,
+}
+
+impl Generator {
+    /// Generates chat for the `#streamchat` channel
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let users = NAMES.iter().map(|name| make_user(&mut rng, name)).collect();
+        Self {
+            rng,
+            channels: vec!["streamchat".to_string()],
+            users,
+            timestamp: 1_552_369_599_175,
+            recent: vec![],
+        }
+    }
+
+    /// Spreads the chat over these channels instead
+    pub fn channels(mut self, channels: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.channels = channels
+            .into_iter()
+            .map(|channel| crate::normalize_channel(channel.as_ref()))
+            .collect();
+        if self.channels.is_empty() {
+            self.channels.push("streamchat".to_string())
+        }
+        self
+    }
+
+    /// The next chat message
+    pub fn message(&mut self) -> Message {
+        self.timestamp += self.rng.range(50, 5000);
+        let channel = self.pick_channel();
+        let user = self.users[self.rng.range(0, self.users.len() as u64) as usize].clone();
+
+        let mut words = vec![];
+        let count = if self.chance(5) {
+            self.rng.range(40, 120)
+        } else {
+            self.rng.range(1, 12)
+        };
+        for _ in 0..count {
+            let word = match self.rng.range(0, 10) {
+                0 | 1 => pick(&mut self.rng, EMOTES).1.to_string(),
+                2 if self.chance(20) => format!("@{}", pick(&mut self.rng, NAMES)),
+                _ => pick(&mut self.rng, WORDS).to_string(),
+            };
+            words.push(word);
+        }
+
+        let bits = if self.chance(2) {
+            let bits = self.rng.range(1, 50) * 100;
+            words.insert(0, format!("Cheer{}", bits));
+            Some(bits)
+        } else {
+            None
+        };
+
+        let id = self.make_id();
+        let mut builder = MessageBuilder::new(&user.name, words.join(" "))
+            .channel(&channel)
+            .userid(user_id(&user.name))
+            .id(id)
+            .room_id(user_id(&channel))
+            .timestamp(self.timestamp)
+            .color(user.color.clone());
+
+        for (kind, version) in &user.badges {
+            builder = builder.badge(kind, version);
+        }
+        if let Some(months) = user.sub_months {
+            builder = builder.subscriber(months);
+        }
+        if channel.eq_ignore_ascii_case(&user.name) {
+            builder = builder.badge("broadcaster", "1");
+        }
+        for (id, name) in EMOTES {
+            if words.iter().any(|word| word == name) {
+                builder = builder.emote(id, name);
+            }
+        }
+        if let Some(bits) = bits {
+            builder = builder.bits(bits);
+        }
+        if self.chance(5) {
+            builder = builder.action();
+        }
+        if self.chance(2) {
+            builder = builder.first_msg();
+        }
+        if self.chance(5) {
+            if let Some(parent) = self.recent.iter().find(|msg| msg.channel == channel) {
+                builder = builder.reply_to(parent);
+            }
+        }
+
+        let msg = builder.build();
+        self.remember(&msg);
+        msg
+    }
+
+    /// The next event. Most of them are messages, with subscriptions, raids, timeouts and
+    /// deletes of the earlier messages mixed in
+    pub fn event(&mut self) -> Event {
+        match self.rng.range(0, 100) {
+            0..=3 => self.subscription(),
+            4 => {
+                let name = pick(&mut self.rng, NAMES).to_string();
+                Event::Raid(Raid {
+                    channel: self.pick_channel(),
+                    name,
+                    viewers: self.rng.range(1, 5000),
+                    tags: Tags::default(),
+                })
+            }
+            5 | 6 if !self.recent.is_empty() => {
+                let index = self.rng.range(0, self.recent.len() as u64) as usize;
+                let msg = self.recent.remove(index);
+                if self.chance(50) {
+                    Event::Delete(crate::event::Delete {
+                        channel: msg.channel,
+                        name: msg.name,
+                        id: msg.id.unwrap_or_default(),
+                        data: msg.data,
+                    })
+                } else {
+                    Event::Timeout(Timeout {
+                        channel: msg.channel,
+                        userid: msg.userid,
+                        name: msg.name,
+                        duration: self.rng.range(1, 600),
+                    })
+                }
+            }
+            _ => Event::Message(self.message()),
+        }
+    }
+
+    fn subscription(&mut self) -> Event {
+        let user = self.users[self.rng.range(0, self.users.len() as u64) as usize].clone();
+        let kind = *pick(
+            &mut self.rng,
+            &[
+                SubKind::Sub,
+                SubKind::Resub,
+                SubKind::Gift,
+                SubKind::MysteryGift,
+            ],
+        );
+        let months = match kind {
+            SubKind::Resub => self.rng.range(2, 60),
+            _ => 1,
+        };
+        let recipient = match kind {
+            SubKind::Gift => Some(pick(&mut self.rng, NAMES).to_string()),
+            _ => None,
+        };
+        let gift_count = match kind {
+            SubKind::MysteryGift => Some(self.rng.range(1, 50)),
+            _ => None,
+        };
+        let data = if kind == SubKind::Resub && self.chance(50) {
+            Some(pick(&mut self.rng, WORDS).to_string())
+        } else {
+            None
+        };
+
+        Event::Subscription(Subscription {
+            channel: self.pick_channel(),
+            kind,
+            userid: user_id(&user.name).to_string(),
+            name: user.name,
+            plan: pick(&mut self.rng, &["Prime", "1000", "2000", "3000"]).to_string(),
+            months,
+            recipient,
+            gift_count,
+            data,
+            tags: Tags::default(),
+        })
+    }
+
+    fn pick_channel(&mut self) -> String {
+        pick(&mut self.rng, &self.channels).clone()
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.rng.range(0, 100) < percent
+    }
+
+    // looks like the uuids twitch uses
+    fn make_id(&mut self) -> String {
+        let (a, b) = (self.rng.next_u64(), self.rng.next_u64());
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            a >> 32,
+            (a >> 16) & 0xFFFF,
+            a & 0xFFFF,
+            b >> 48,
+            b & 0xFFFF_FFFF_FFFF
+        )
+    }
+
+    fn remember(&mut self, msg: &Message) {
+        const RECENT: usize = 32;
+        if self.recent.len() == RECENT {
+            self.recent.remove(0);
+        }
+        self.recent.push(msg.clone());
+    }
+}
+
+/// Endless messages
+impl Iterator for Generator {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.message())
+    }
+}
+
+fn make_user(rng: &mut Rng, name: &str) -> User {
+    let color = if rng.range(0, 2) == 0 {
+        pick(rng, &NAMED_COLORS).1.into()
+    } else {
+        let n = rng.next_u64();
+        Rgb(n as u8, (n >> 8) as u8, (n >> 16) as u8).into()
+    };
+
+    let mut badges = vec![];
+    match rng.range(0, 100) {
+        0..=9 => badges.push(("moderator", "1".to_string())),
+        10..=14 => badges.push(("vip", "1".to_string())),
+        _ => {}
+    }
+    let sub_months = match rng.range(0, 100) {
+        0..=39 => Some(rng.range(1, 60)),
+        _ => None,
+    };
+    match rng.range(0, 10) {
+        0 => badges.push(("premium", "1".to_string())),
+        1 => badges.push(("bits", (rng.range(1, 100) * 100).to_string())),
+        _ => {}
+    }
+
+    User {
+        name: name.to_string(),
+        color,
+        badges,
+        sub_months,
+    }
+}
+
+fn pick<'a, T>(rng: &mut Rng, items: &'a [T]) -> &'a T {
+    &items[rng.range(0, items.len() as u64) as usize]
+}
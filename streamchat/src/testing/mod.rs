@@ -0,0 +1,316 @@
+//! Utilities for testing things built on top of `streamchat`
+//!
+//! [`MockServer`](./struct.MockServer.html) is an in-process stand-in for Twitch's IRC server.
+//! Point [`connection::connect_to`](../connection/fn.connect_to.html) at its address and then
+//! script the chat from the returned [`MockConnection`](./struct.MockConnection.html).
+//!
+//! [`MessageBuilder`](./struct.MessageBuilder.html) fills in a `Message` and its tags from a
+//! few fields, and [`Generator`](./struct.Generator.html) makes up realistic chat from a seed
+use crossbeam_channel as channel;
+use std::io::{self, prelude::*, BufReader};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+mod builder;
+pub use self::builder::MessageBuilder;
+
+mod generator;
+pub use self::generator::Generator;
+
+/// The user-id given to the registered user
+pub const MOCK_USER_ID: u64 = 12345;
+
+/// How long the helpers wait for the client to say something
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A fake Twitch IRC server, listening on a random local port
+pub struct MockServer {
+    addr: SocketAddr,
+    connections: channel::Receiver<MockConnection>,
+}
+
+impl MockServer {
+    /// Starts the server. Only clients that register with `token`, or anonymously as
+    /// `justinfanNNNNN`, will be accepted
+    pub fn start(token: impl ToString) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let token = token.to_string();
+
+        let (tx, rx) = channel::unbounded();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::warn!("mock server cannot accept: {}", err);
+                        continue;
+                    }
+                };
+
+                let (token, tx) = (token.clone(), tx.clone());
+                thread::spawn(move || match MockConnection::register(stream, &token) {
+                    Ok(Some(conn)) => {
+                        let _ = tx.send(conn);
+                    }
+                    Ok(None) => log::debug!("mock server rejected a login"),
+                    Err(err) => log::warn!("mock server registration failed: {}", err),
+                });
+            }
+        });
+
+        log::debug!("mock server listening on: {}", addr);
+        Ok(Self {
+            addr,
+            connections: rx,
+        })
+    }
+
+    /// The address to connect to
+    pub fn address(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Waits for the next client to successfully register
+    pub fn accept(&self) -> Option<MockConnection> {
+        self.accept_timeout(DEFAULT_TIMEOUT)
+    }
+
+    pub fn accept_timeout(&self, timeout: Duration) -> Option<MockConnection> {
+        self.connections.recv_timeout(timeout).ok()
+    }
+}
+
+impl std::fmt::Debug for MockServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockServer")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+/// A registered client of the [`MockServer`](./struct.MockServer.html)
+pub struct MockConnection {
+    nick: String,
+    stream: TcpStream,
+    lines: channel::Receiver<String>,
+}
+
+impl MockConnection {
+    fn register(stream: TcpStream, token: &str) -> io::Result<Option<Self>> {
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let (mut pass, mut nick) = (None, None);
+        let mut line = String::new();
+        while pass.is_none() || nick.is_none() {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            let line = line.trim_end();
+            log::trace!("mock <- {}", line);
+            let (cmd, rest) = split_command(line);
+            match cmd {
+                "PASS" => pass = Some(rest.to_string()),
+                "NICK" => nick = Some(rest.to_string()),
+                "CAP" => {
+                    let caps = rest
+                        .split_once(':')
+                        .map(|(_, caps)| caps)
+                        .unwrap_or_default();
+                    write_line(&mut writer, &format!(":tmi.twitch.tv CAP * ACK :{}", caps))?
+                }
+                _ => log::warn!("unexpected line during registration: {}", line),
+            }
+        }
+
+        let (pass, nick) = (pass.unwrap(), nick.unwrap());
+        let anonymous = is_anonymous(&nick);
+        if pass != token && !anonymous {
+            write_line(
+                &mut writer,
+                ":tmi.twitch.tv NOTICE * :Login authentication failed",
+            )?;
+            let _ = stream.shutdown(Shutdown::Both);
+            return Ok(None);
+        }
+
+        write_line(
+            &mut writer,
+            &format!(":tmi.twitch.tv 001 {} :Welcome, GLHF!", nick),
+        )?;
+        // like twitch, anonymous users don't get a GLOBALUSERSTATE
+        if !anonymous {
+            write_line(
+                &mut writer,
+                &format!(
+                    "@badge-info=;badges=;color=#FF4500;display-name={};emote-sets=0;\
+                     user-id={};user-type= :tmi.twitch.tv GLOBALUSERSTATE",
+                    nick, MOCK_USER_ID
+                ),
+            )?;
+        }
+
+        let (tx, rx) = channel::unbounded();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(..) => break,
+                };
+                log::trace!("mock <- {}", line);
+                if let ("PING", token) = split_command(&line) {
+                    let _ = write_line(&mut writer, &format!("PONG {}", token));
+                    continue;
+                }
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Some(Self {
+            nick,
+            stream,
+            lines: rx,
+        }))
+    }
+
+    /// The nick the client registered with
+    pub fn nick(&self) -> &str {
+        &self.nick
+    }
+
+    /// Whether the client registered as `justinfanNNNNN`
+    pub fn is_anonymous(&self) -> bool {
+        is_anonymous(&self.nick)
+    }
+
+    /// Sends a raw line to the client. The line ending is added
+    pub fn send_raw(&mut self, line: impl AsRef<str>) -> io::Result<()> {
+        write_line(&mut self.stream, line.as_ref())
+    }
+
+    /// Sends a `PRIVMSG` from `user` to `channel`
+    pub fn privmsg(
+        &mut self,
+        tags: &[(&str, &str)],
+        user: &str,
+        channel: &str,
+        data: &str,
+    ) -> io::Result<()> {
+        self.send_raw(format!(
+            "{}:{user}!{user}@{user}.tmi.twitch.tv PRIVMSG #{} :{}",
+            format_tags(tags),
+            channel.trim_start_matches('#'),
+            data,
+            user = user
+        ))
+    }
+
+    /// Sends a `USERNOTICE` (subs, raids, ..) to `channel`. The kind is taken from the `msg-id` tag
+    pub fn usernotice(
+        &mut self,
+        tags: &[(&str, &str)],
+        channel: &str,
+        data: Option<&str>,
+    ) -> io::Result<()> {
+        self.send_raw(format!(
+            "{}:tmi.twitch.tv USERNOTICE #{}{}",
+            format_tags(tags),
+            channel.trim_start_matches('#'),
+            data.map(|s| format!(" :{}", s)).unwrap_or_default()
+        ))
+    }
+
+    /// Sends a `CLEARCHAT` for `user`, or for the whole `channel` if there is no user
+    pub fn clearchat(
+        &mut self,
+        tags: &[(&str, &str)],
+        channel: &str,
+        user: Option<&str>,
+    ) -> io::Result<()> {
+        self.send_raw(format!(
+            "{}:tmi.twitch.tv CLEARCHAT #{}{}",
+            format_tags(tags),
+            channel.trim_start_matches('#'),
+            user.map(|s| format!(" :{}", s)).unwrap_or_default()
+        ))
+    }
+
+    /// Waits for the next line the client sent, skipping `PING`s
+    pub fn recv(&self) -> Option<String> {
+        self.lines.recv_timeout(DEFAULT_TIMEOUT).ok()
+    }
+
+    /// Waits for a line starting with `command`, discarding everything before it
+    pub fn wait_for(&self, command: &str) -> Option<String> {
+        std::iter::from_fn(|| self.recv()).find(|line| split_command(line).0 == command)
+    }
+
+    /// Drops the connection, like Twitch does when it cycles its servers
+    pub fn disconnect(self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl std::fmt::Debug for MockConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockConnection")
+            .field("nick", &self.nick)
+            .finish()
+    }
+}
+
+fn is_anonymous(nick: &str) -> bool {
+    nick.strip_prefix("justinfan")
+        .map(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or(false)
+}
+
+fn split_command(line: &str) -> (&str, &str) {
+    let mut parts = line.splitn(2, ' ');
+    (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    )
+}
+
+fn write_line(w: &mut impl Write, line: &str) -> io::Result<()> {
+    log::trace!("mock -> {}", line);
+    w.write_all(line.as_bytes())?;
+    w.write_all(b"\r\n")?;
+    w.flush()
+}
+
+fn format_tags(tags: &[(&str, &str)]) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+
+    let tags = tags
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, escape_tag(v)))
+        .collect::<Vec<_>>()
+        .join(";");
+    format!("@{} ", tags)
+}
+
+fn escape_tag(value: &str) -> String {
+    value
+        .chars()
+        .fold(String::with_capacity(value.len()), |mut s, c| {
+            match c {
+                ';' => s.push_str("\\:"),
+                ' ' => s.push_str("\\s"),
+                '\\' => s.push_str("\\\\"),
+                '\r' => s.push_str("\\r"),
+                '\n' => s.push_str("\\n"),
+                c => s.push(c),
+            }
+            s
+        })
+}
@@ -0,0 +1,479 @@
+//! The types used on the wire, independent of any IRC library
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+
+/// An RGB triplet, serialized as `#RRGGBB`
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl std::fmt::Display for Rgb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Rgb(r, g, b) = self;
+        write!(f, "#{:02X}{:02X}{:02X}", r, g, b)
+    }
+}
+
+impl std::str::FromStr for Rgb {
+    type Err = ParseColorError;
+
+    /// Parses `#RRGGBB` or `RRGGBB`
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let s = input.trim_start_matches('#');
+        if s.len() != 6 || !s.is_ascii() {
+            return Err(ParseColorError(input.to_string()));
+        }
+        let part = |i| u8::from_str_radix(&s[i..i + 2], 16);
+        match (part(0), part(2), part(4)) {
+            (Ok(r), Ok(g), Ok(b)) => Ok(Rgb(r, g, b)),
+            _ => Err(ParseColorError(input.to_string())),
+        }
+    }
+}
+
+impl Serialize for Rgb {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // older files stored these as [r, g, b]
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Hex(String),
+            Array(u8, u8, u8),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Hex(s) => s.parse().map_err(de::Error::custom),
+            Repr::Array(r, g, b) => Ok(Rgb(r, g, b)),
+        }
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Rgb {
+    fn schema_name() -> String {
+        "Rgb".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::*;
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some("^#[0-9A-F]{6}$".to_string()),
+                ..Default::default()
+            })),
+            metadata: Some(Box::new(Metadata {
+                description: Some("`#RRGGBB`".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Twitch's named colors
+pub const NAMED_COLORS: [(&str, Rgb); 15] = [
+    ("Blue", Rgb(0x00, 0x00, 0xFF)),
+    ("BlueViolet", Rgb(0x8A, 0x2B, 0xE2)),
+    ("CadetBlue", Rgb(0x5F, 0x9E, 0xA0)),
+    ("Chocolate", Rgb(0xD2, 0x69, 0x1E)),
+    ("Coral", Rgb(0xFF, 0x7F, 0x50)),
+    ("DodgerBlue", Rgb(0x1E, 0x90, 0xFF)),
+    ("Firebrick", Rgb(0xB2, 0x22, 0x22)),
+    ("GoldenRod", Rgb(0xDA, 0xA5, 0x20)),
+    ("Green", Rgb(0x00, 0x80, 0x00)),
+    ("HotPink", Rgb(0xFF, 0x69, 0xB4)),
+    ("OrangeRed", Rgb(0xFF, 0x45, 0x00)),
+    ("Red", Rgb(0xFF, 0x00, 0x00)),
+    ("SeaGreen", Rgb(0x2E, 0x8B, 0x57)),
+    ("SpringGreen", Rgb(0x00, 0xFF, 0x7F)),
+    ("YellowGreen", Rgb(0x9A, 0xCD, 0x32)),
+];
+
+/// A user color.
+///
+/// `name` is set when it is one of Twitch's [named colors](./constant.NAMED_COLORS.html)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Color {
+    pub rgb: Rgb,
+    pub name: Option<String>,
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Rgb(0xFF, 0xFF, 0xFF).into()
+    }
+}
+
+impl From<Rgb> for Color {
+    fn from(rgb: Rgb) -> Self {
+        let name = NAMED_COLORS
+            .iter()
+            .find(|(_, c)| *c == rgb)
+            .map(|(name, _)| name.to_string());
+        Self { rgb, name }
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = ParseColorError;
+
+    /// Parses `#RRGGBB`, `RRGGBB` or one of the named colors (case insensitive)
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(input))
+            .map(|&(_, rgb)| rgb.into())
+            .map(Ok)
+            .unwrap_or_else(|| input.parse::<Rgb>().map(Into::into))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // version 1 used twitchchat's representation: "OrangeRed" or {"Turbo": [r, g, b]}
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Current {
+                rgb: Rgb,
+                name: Option<String>,
+            },
+            Named(String),
+            Turbo {
+                #[serde(rename = "Turbo")]
+                turbo: (u8, u8, u8),
+            },
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Current { rgb, name } => Ok(Self { rgb, name }),
+            Repr::Named(name) => name.parse().map_err(de::Error::custom),
+            Repr::Turbo { turbo: (r, g, b) } => Ok(Self {
+                rgb: Rgb(r, g, b),
+                name: None,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseColorError(String);
+
+impl std::fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid color: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+/// A chat badge, e.g. `subscriber/12` is `kind: "subscriber", version: "12"`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Badge {
+    #[serde(deserialize_with = "badge_kind")]
+    pub kind: String,
+    #[serde(alias = "data")]
+    pub version: String,
+}
+
+impl Badge {
+    /// Parses the `badges` tag
+    pub fn parse_list(input: &str) -> Vec<Self> {
+        input
+            .split(',')
+            .filter_map(|badge| {
+                let mut parts = badge.splitn(2, '/');
+                let kind = parts.next().filter(|s| !s.is_empty())?;
+                Some(Self {
+                    kind: kind.to_string(),
+                    version: parts.next().unwrap_or_default().to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+// version 1 used twitchchat's variant names, e.g. "GlobalMod" for "global_mod", and
+// {"Unknown": "kind"} for the others
+fn badge_kind<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Kind(String),
+        Unknown {
+            #[serde(rename = "Unknown")]
+            unknown: String,
+        },
+    }
+
+    let kind = match Repr::deserialize(deserializer)? {
+        Repr::Kind(kind) => kind,
+        Repr::Unknown { unknown } => return Ok(unknown),
+    };
+    if kind.chars().all(|c| c.is_ascii_uppercase()) {
+        return Ok(kind.to_ascii_lowercase());
+    }
+    Ok(kind
+        .chars()
+        .enumerate()
+        .fold(String::new(), |mut s, (i, c)| {
+            if c.is_ascii_uppercase() {
+                if i > 0 {
+                    s.push('_');
+                }
+                s.push(c.to_ascii_lowercase());
+            } else {
+                s.push(c)
+            }
+            s
+        }))
+}
+
+/// How trusted a user is in a channel, from their badges. Roles compare by trust, so
+/// `role >= Role::Vip` is a VIP, a moderator or the broadcaster.
+///
+/// Twitch doesn't say who follows a channel, so followers are `Everyone`
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    Everyone,
+    /// A subscriber or a founder
+    Subscriber,
+    Vip,
+    /// A moderator of the channel, or Twitch staff
+    Moderator,
+    Broadcaster,
+}
+
+impl Role {
+    /// The highest role any of the badges give
+    pub fn from_badges(badges: &[Badge]) -> Self {
+        badges
+            .iter()
+            .map(|badge| match badge.kind.as_str() {
+                "broadcaster" => Role::Broadcaster,
+                "moderator" | "global_mod" | "admin" | "staff" => Role::Moderator,
+                "vip" => Role::Vip,
+                "subscriber" | "founder" => Role::Subscriber,
+                _ => Role::Everyone,
+            })
+            .max()
+            .unwrap_or(Role::Everyone)
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = ParseRoleError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let role = match input.to_ascii_lowercase().as_str() {
+            "everyone" => Role::Everyone,
+            "subscriber" | "sub" => Role::Subscriber,
+            "vip" => Role::Vip,
+            "moderator" | "mod" => Role::Moderator,
+            "broadcaster" => Role::Broadcaster,
+            _ => return Err(ParseRoleError(input.to_string())),
+        };
+        Ok(role)
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Role::Everyone => "everyone",
+            Role::Subscriber => "subscriber",
+            Role::Vip => "vip",
+            Role::Moderator => "moderator",
+            Role::Broadcaster => "broadcaster",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseRoleError(String);
+
+impl std::fmt::Display for ParseRoleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid role: {}. expected everyone, subscriber, vip, moderator or broadcaster",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseRoleError {}
+
+/// An emote and where it appears in the message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Emote {
+    #[serde(deserialize_with = "emote_id")]
+    pub id: String,
+    pub ranges: Vec<Range>,
+}
+
+/// Inclusive codepoint indices
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Range {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Emote {
+    /// Parses the `emotes` tag, e.g. `25:0-4,12-16/1902:6-10`
+    pub fn parse_list(input: &str) -> Vec<Self> {
+        input
+            .split('/')
+            .filter_map(|emote| {
+                let mut parts = emote.splitn(2, ':');
+                let id = parts.next().filter(|s| !s.is_empty())?;
+                let ranges = parts
+                    .next()?
+                    .split(',')
+                    .filter_map(|range| {
+                        let mut parts = range.splitn(2, '-');
+                        Some(Range {
+                            start: parts.next()?.parse().ok()?,
+                            end: parts.next()?.parse().ok()?,
+                        })
+                    })
+                    .collect();
+                Some(Self {
+                    id: id.to_string(),
+                    ranges,
+                })
+            })
+            .collect()
+    }
+}
+
+// version 1 had numeric ids
+fn emote_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Number(u64),
+        String(String),
+    }
+
+    Ok(match Repr::deserialize(deserializer)? {
+        Repr::Number(id) => id.to_string(),
+        Repr::String(id) => id,
+    })
+}
+
+/// The IRCv3 tags attached to a message
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(transparent)]
+pub struct Tags(pub HashMap<String, String>);
+
+impl Tags {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+}
+
+#[cfg(feature = "twitch")]
+mod twitch_conversions {
+    use super::*;
+    use crate::twitch;
+
+    impl From<twitch::RGB> for Rgb {
+        fn from(twitch::RGB(r, g, b): twitch::RGB) -> Self {
+            Rgb(r, g, b)
+        }
+    }
+
+    impl From<twitch::RGB> for Color {
+        fn from(rgb: twitch::RGB) -> Self {
+            Rgb::from(rgb).into()
+        }
+    }
+
+    impl From<twitch::Color> for Color {
+        fn from(color: twitch::Color) -> Self {
+            twitch::RGB::from(color).into()
+        }
+    }
+
+    impl From<&twitch::Tags> for Tags {
+        fn from(tags: &twitch::Tags) -> Self {
+            Tags(tags.inner().clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emote_list() {
+        let emotes = Emote::parse_list("25:0-4,12-16/emotesv2_abc:6-10");
+        assert_eq!(
+            emotes,
+            vec![
+                Emote {
+                    id: "25".into(),
+                    ranges: vec![Range { start: 0, end: 4 }, Range { start: 12, end: 16 }],
+                },
+                Emote {
+                    id: "emotesv2_abc".into(),
+                    ranges: vec![Range { start: 6, end: 10 }],
+                },
+            ]
+        );
+
+        assert!(Emote::parse_list("").is_empty());
+        // the broken ranges are dropped, an emote without a `:` is too
+        assert_eq!(
+            Emote::parse_list("25:0-4,x-2,7/1902"),
+            vec![Emote {
+                id: "25".into(),
+                ranges: vec![Range { start: 0, end: 4 }],
+            }]
+        );
+    }
+
+    #[test]
+    fn emote_ids_can_be_numbers() {
+        let emote: Emote = serde_json::from_str(r#"{"id":25,"ranges":[]}"#).unwrap();
+        assert_eq!(emote.id, "25");
+    }
+
+    #[test]
+    fn badge_list() {
+        let badges = Badge::parse_list("broadcaster/1,subscriber/3012,glhf-pledge");
+        let badges = badges
+            .iter()
+            .map(|badge| (badge.kind.as_str(), badge.version.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            badges,
+            vec![
+                ("broadcaster", "1"),
+                ("subscriber", "3012"),
+                ("glhf-pledge", "")
+            ]
+        );
+        assert!(Badge::parse_list("").is_empty());
+    }
+}
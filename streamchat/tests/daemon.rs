@@ -0,0 +1,164 @@
+#![cfg(all(feature = "daemon", feature = "testing"))]
+//! Runs the daemon against a `MockServer`, with a transport that records what it is sent
+
+use crossbeam_channel as channel;
+use std::time::Duration;
+use streamchat::connection::{Backoff, Status};
+use streamchat::daemon::{Builder, Config};
+use streamchat::testing::MockServer;
+use streamchat::{Event, Message, Transport};
+
+const TOKEN: &str = "oauth:mock";
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Recorder(channel::Sender<(u64, Event)>);
+
+impl Transport for Recorder {
+    fn send(&mut self, _data: Message) -> Result<(), Box<dyn std::error::Error>> {
+        unreachable!("the daemon sends sequenced events")
+    }
+
+    fn send_sequenced(&mut self, seq: u64, event: Event) -> Result<(), Box<dyn std::error::Error>> {
+        self.0.send((seq, event)).map_err(Into::into)
+    }
+}
+
+fn config(token: &str) -> Config {
+    Config {
+        nick: "museun".into(),
+        oauth_token: token.into(),
+        channels: vec!["#museun".into()],
+    }
+}
+
+fn backoff(max_attempts: u32) -> Backoff {
+    Backoff {
+        initial: Duration::from_millis(10),
+        max: Duration::from_millis(50),
+        factor: 2,
+        max_attempts: Some(max_attempts),
+    }
+}
+
+fn tags(name: &str) -> Vec<(&str, &str)> {
+    vec![
+        ("user-id", "1004"),
+        ("display-name", name),
+        ("badges", "subscriber/3"),
+        ("color", "#FF4500"),
+        ("tmi-sent-ts", "1565000000000"),
+    ]
+}
+
+fn message(events: &channel::Receiver<(u64, Event)>) -> (u64, Message) {
+    match events.recv_timeout(TIMEOUT).expect("an event") {
+        (seq, Event::Message(msg)) => (seq, msg),
+        (_, event) => panic!("expected a message, got {:?}", event),
+    }
+}
+
+#[test]
+fn relays_messages_and_reconnects() {
+    let server = MockServer::start(TOKEN).unwrap();
+    let addr = server.address();
+    let (events_tx, events) = channel::unbounded();
+    let (status_tx, status) = channel::unbounded();
+
+    std::thread::spawn(move || {
+        Builder::new(config(TOKEN))
+            .address(addr)
+            .backoff(backoff(5))
+            .status(status_tx)
+            .transport(Recorder(events_tx))
+            .command("hello", |msg, _args| Some(format!("hello {}", msg.name)))
+            .run()
+    });
+
+    let mut conn = server.accept().expect("the daemon connects");
+    assert!(conn.wait_for("JOIN").unwrap().contains("#museun"));
+    assert_eq!(
+        status.recv_timeout(TIMEOUT).unwrap(),
+        Status::Connecting { attempt: 1 }
+    );
+    assert_eq!(
+        status.recv_timeout(TIMEOUT).unwrap(),
+        Status::Connected { anonymous: false }
+    );
+
+    conn.privmsg(&tags("Foo"), "foo", "museun", "!hello there")
+        .unwrap();
+    let (seq, msg) = message(&events);
+    assert_eq!(seq, 1);
+    assert_eq!((msg.channel.as_str(), msg.name.as_str()), ("museun", "Foo"));
+    assert_eq!(msg.data, "!hello there");
+    // the reply to the command
+    assert_eq!(
+        conn.wait_for("PRIVMSG").unwrap(),
+        "PRIVMSG #museun :hello Foo"
+    );
+
+    conn.disconnect();
+    match status.recv_timeout(TIMEOUT).unwrap() {
+        Status::BackingOff { attempt: 1, .. } => {}
+        status => panic!("expected a back off, got {:?}", status),
+    }
+
+    // the sequence ids keep going, and replies use the new connection
+    let mut conn = server.accept().expect("the daemon reconnects");
+    conn.wait_for("JOIN").unwrap();
+    conn.privmsg(&tags("Bar"), "bar", "museun", "!hello")
+        .unwrap();
+    let (seq, msg) = message(&events);
+    assert_eq!((seq, msg.name.as_str()), (2, "Bar"));
+    assert_eq!(
+        conn.wait_for("PRIVMSG").unwrap(),
+        "PRIVMSG #museun :hello Bar"
+    );
+}
+
+#[test]
+fn gives_up_on_a_rejected_login() {
+    let server = MockServer::start(TOKEN).unwrap();
+    let (status_tx, status) = channel::unbounded();
+
+    let err = Builder::new(config("oauth:wrong"))
+        .address(server.address())
+        .backoff(backoff(5))
+        .status(status_tx)
+        .run()
+        .unwrap_err();
+    assert!(err.is_fatal(), "{}", err);
+
+    let last = status.try_iter().last();
+    match last {
+        Some(Status::GaveUp { .. }) => {}
+        status => panic!("expected to give up, got {:?}", status),
+    }
+}
+
+#[test]
+fn anonymous_connections_skip_commands() {
+    let server = MockServer::start(TOKEN).unwrap();
+    let addr = server.address();
+    let (events_tx, events) = channel::unbounded();
+
+    std::thread::spawn(move || {
+        Builder::new(config(""))
+            .address(addr)
+            .transport(Recorder(events_tx))
+            .command("hello", |msg, _args| Some(format!("hello {}", msg.name)))
+            .run()
+    });
+
+    let mut conn = server.accept().expect("the daemon connects");
+    assert!(conn.is_anonymous());
+    conn.wait_for("JOIN").unwrap();
+
+    conn.privmsg(&tags("Foo"), "foo", "museun", "!hello")
+        .unwrap();
+    let (seq, msg) = message(&events);
+    assert_eq!((seq, msg.data.as_str()), (1, "!hello"));
+
+    // anonymous logins can't send, so there is no reply
+    assert!(conn.wait_for("PRIVMSG").is_none());
+}
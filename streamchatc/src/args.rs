@@ -1,6 +1,7 @@
 use gumdrop::Options;
 use lexical_bool::LexicalBool;
 use serde::{Deserialize, Serialize};
+use streamchat::filter::Filter;
 
 pub(crate) const ENV_KEY: &'static str = "STREAMCHAT_TWITCH_OAUTH_TOKEN";
 
@@ -21,7 +22,10 @@ pub struct Args {
     #[options(help = "right fringe color", no_short, meta = "#RRGGBB")]
     pub right_color: Option<String>,
 
-    #[options(help = "address of the streamchatd instance", meta = "ADDR")]
+    #[options(
+        help = "address of the streamchatd instance, or unix:PATH",
+        meta = "ADDR"
+    )]
     pub address: Option<String>,
 
     #[options(
@@ -51,8 +55,26 @@ pub struct Args {
     #[options(help = "your twitch name", no_short, meta = "TWITCH_NAME")]
     pub nick: Option<String>,
 
-    #[options(help = "the channel to join", no_short, meta = "TWITCH_CHANNEL")]
-    pub channel: Option<String>,
+    #[options(
+        help = "a channel to show, can be repeated. defaults to all of the daemon's channels",
+        no_short,
+        meta = "TWITCH_CHANNEL"
+    )]
+    pub channel: Vec<String>,
+
+    #[options(
+        help = "hide the messages matching this filter",
+        no_short,
+        meta = "FILTER"
+    )]
+    pub hide: Option<Filter>,
+
+    #[options(
+        help = "highlight the messages matching this filter",
+        no_short,
+        meta = "FILTER"
+    )]
+    pub highlight: Option<Filter>,
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
@@ -68,13 +90,20 @@ pub struct Config {
     pub nick_max: usize,
     pub left_fringe: Fringe,
     pub right_fringe: Fringe,
+    /// channels to join when standalone, or to show from the daemon. empty is all of them
+    #[serde(default)]
+    pub channels: Vec<String>,
+    /// messages matching this aren't shown, see `streamchat::filter`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hide: Option<Filter>,
+    /// messages matching this are shown in bold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<Filter>,
 
     // for overrides
     #[serde(skip)]
     pub nick: String,
     #[serde(skip)]
-    pub channel: String,
-    #[serde(skip)]
     pub token: String,
 }
 
@@ -92,8 +121,10 @@ impl Default for Config {
                 fringe: "⤶".to_string(),
                 color: "#FFFF00".to_string(),
             },
+            channels: Default::default(),
+            hide: None,
+            highlight: None,
             nick: Default::default(),
-            channel: Default::default(),
             token: Default::default(),
         }
     }
@@ -147,7 +178,11 @@ macro_rules! verify {
 
 impl Config {
     pub fn create_config_from_args(args: &Args) -> Config {
-        let token = token_from_env();
+        let token = if args.standalone {
+            token_from_env()
+        } else {
+            String::new()
+        };
 
         Config {
             address: if args.standalone {
@@ -155,8 +190,11 @@ impl Config {
             } else {
                 verify!(&args, address)
             },
-            nick: verify!(&args, nick),
-            channel: verify!(&args, channel),
+            nick: if args.standalone && token.is_empty() {
+                args.nick.clone().unwrap_or_default()
+            } else {
+                verify!(&args, nick)
+            },
             token,
             ..Default::default()
         }
@@ -185,12 +223,14 @@ impl Config {
         if args.standalone {
             config.token = token_from_env();
             verify!(not=> &args, address);
-            config.nick = verify!(&args, nick);
-            config.channel = verify!(&args, channel);
+            config.nick = if config.token.is_empty() {
+                args.nick.clone().unwrap_or_default()
+            } else {
+                verify!(&args, nick)
+            };
         } else {
             verify!(not=> &args, address);
             verify!(not=> &args, nick);
-            verify!(not=> &args, channel);
         }
 
         config.merge_args(&args)
@@ -219,13 +259,33 @@ impl Config {
         merge!(buffer_max);
         merge!(nick_max);
 
+        if !args.channel.is_empty() {
+            self.channels = args.channel.clone();
+        }
+
+        if args.hide.is_some() {
+            self.hide = args.hide.clone();
+        }
+        if args.highlight.is_some() {
+            self.highlight = args.highlight.clone();
+        }
+
+        if args.standalone && self.channels.is_empty() {
+            eprintln!("error! the `--channel` flag must be used when using standalone");
+            std::process::exit(1);
+        }
+
         self
     }
 }
 
+// without a token, standalone mode reads chat anonymously
 fn token_from_env() -> String {
     std::env::var(ENV_KEY).unwrap_or_else(|_| {
-        eprintln!("when not using the config file, the env variable `{}` must be set to your Twitch OAUTH token", ENV_KEY);
-        std::process::exit(1);
+        eprintln!(
+            "the env variable `{}` isn't set, so reading chat anonymously",
+            ENV_KEY
+        );
+        String::new()
     })
 }
@@ -1,12 +1,16 @@
+use streamchat::{client, connection};
+
 #[derive(Debug)]
 pub enum Error {
-    Connect(std::io::Error),
+    Twitch(connection::Error),
+    Daemon(client::Error),
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::Connect(err) => write!(f, "cannot connect: {}", err),
+            Error::Twitch(err) => write!(f, "twitch: {}", err),
+            Error::Daemon(err) => write!(f, "streamchatd: {}", err),
         }
     }
 }
@@ -14,7 +18,8 @@ impl std::fmt::Display for Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Error::Connect(err) => Some(err as &(dyn std::error::Error)),
+            Error::Twitch(err) => Some(err as &(dyn std::error::Error)),
+            Error::Daemon(err) => Some(err as &(dyn std::error::Error)),
         }
     }
 }
@@ -1,10 +1,10 @@
-use streamchat::twitch;
+use streamchat::Rgb;
 use unicode_width::UnicodeWidthStr as _;
 
 #[derive(Debug, Clone)]
 pub struct Fringe<'a> {
     data: &'a str,
-    color: twitch::RGB,
+    color: Rgb,
     width: usize,
 }
 
@@ -23,7 +23,7 @@ impl<'a> Fringe<'a> {
         self.width
     }
 
-    pub fn color(&self) -> twitch::RGB {
+    pub fn color(&self) -> Rgb {
         self.color
     }
 
@@ -5,11 +5,13 @@ use window::Window;
 mod args;
 use args::{Args, Config};
 
+mod error;
+use error::Error;
+
 use configurable::Configurable as _;
 use crossbeam_channel as channel;
 use gumdrop::Options as _;
-use std::io::{BufRead, BufReader};
-use streamchat::connection as conn;
+use streamchat::{client::DaemonClient, connection as conn};
 use yansi::{Color, Paint};
 
 fn main() {
@@ -41,7 +43,7 @@ fn main() {
     } {
         Ok(client) => client,
         Err(err) => {
-            eprintln!("cannot connect: {}", err);
+            eprintln!("{}", err);
             std::process::exit(1)
         }
     };
@@ -57,30 +59,44 @@ fn main() {
 }
 
 struct Client {
-    handle: std::thread::JoinHandle<Result<(), conn::Error>>,
-    recv: channel::Receiver<streamchat::Message>,
+    handle: std::thread::JoinHandle<Result<(), Error>>,
+    recv: channel::Receiver<streamchat::Event>,
 }
 
 impl Client {
-    fn standalone(config: &Config) -> Result<Self, conn::Error> {
-        let conn = conn::connect_to_twitch(&config.nick, &config.token, &config.channel)?;
+    fn standalone(config: &Config) -> Result<Self, Error> {
+        let supervisor = conn::Supervisor::new(&config.nick, &config.token, &config.channels);
 
         let (tx, rx) = channel::unbounded();
-        let handle = std::thread::spawn(move || conn::read_until_end(conn, tx));
+        let (status_tx, status_rx) = channel::unbounded();
+        let handle =
+            std::thread::spawn(move || supervisor.run(tx, status_tx).map_err(Error::Twitch));
+
+        std::thread::spawn(move || {
+            for status in status_rx {
+                eprintln!("twitch: {}", status);
+            }
+        });
 
         Ok(Self { handle, recv: rx })
     }
 
-    fn connect_to_server(config: &Config) -> Result<Self, conn::Error> {
-        let conn = std::net::TcpStream::connect(&config.address)?;
+    fn connect_to_server(config: &Config) -> Result<Self, Error> {
+        let client = connect_daemon(&config.address)
+            .map_err(Error::Daemon)?
+            .subscribe(&config.channels);
 
         let (tx, rx) = channel::unbounded();
         let handle = std::thread::spawn(move || {
-            let mut lines = BufReader::new(conn).lines();
-            while let Some(Ok(line)) = lines.next() {
-                let msg: streamchat::Message = serde_json::from_str(&line).expect("valid json");
-                if tx.send(msg.into()).is_err() {
-                    break;
+            for event in client {
+                match event {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) if err.is_recoverable() => log::warn!("skipping line: {}", err),
+                    Err(err) => return Err(Error::Daemon(err)),
                 }
             }
             Ok(())
@@ -89,7 +105,18 @@ impl Client {
         Ok(Self { handle, recv: rx })
     }
 
-    fn wait_for_end(self) -> Result<(), conn::Error> {
+    fn wait_for_end(self) -> Result<(), Error> {
         self.handle.join().unwrap() // thread unwind
     }
 }
+
+// `unix:/path` is a unix socket, anything else is a tcp address
+fn connect_daemon(address: &str) -> Result<DaemonClient, streamchat::client::Error> {
+    #[cfg(unix)]
+    {
+        if let Some(path) = address.strip_prefix("unix:") {
+            return DaemonClient::connect_unix(path);
+        }
+    }
+    DaemonClient::connect(address)
+}
@@ -1,6 +1,7 @@
 use super::*;
 use crate::layout::Fringe;
-use streamchat::twitch;
+use streamchat::event::{Ban, Clear, SubKind, Subscription, Timeout};
+use streamchat::{Event, Rgb};
 
 use std::borrow::Cow;
 
@@ -12,7 +13,7 @@ use unicode_width::UnicodeWidthStr as _;
 pub struct Window;
 
 impl Window {
-    pub fn run(config: Config, messages: channel::Receiver<streamchat::Message>) {
+    pub fn run(config: Config, events: channel::Receiver<streamchat::Event>) {
         use std::time::Duration;
 
         let term = console::Term::stdout();
@@ -31,8 +32,47 @@ impl Window {
 
         loop {
             channel::select! {
-                recv(messages) -> msg => {
-                    let msg = match msg { Ok(msg) => msg.into(), Err(..) => break };
+                recv(events) -> event => {
+                    let event = match event { Ok(event) => event, Err(..) => break };
+                    let msg = match event {
+                        Event::Message(msg) => {
+                            if matches!(&config.hide, Some(hide) if hide.matches(&msg)) {
+                                continue;
+                            }
+                            let highlight =
+                                matches!(&config.highlight, Some(rule) if rule.matches(&msg));
+                            DisplayMessage { highlight, ..msg.into() }
+                        }
+                        Event::Subscription(sub) => {
+                            DisplayMessage::notice(&sub.channel, describe_sub(&sub))
+                        }
+                        Event::Raid(raid) => DisplayMessage::notice(&raid.channel, format!(
+                            "{} is raiding with {} viewers",
+                            raid.name, raid.viewers
+                        )),
+                        Event::Delete(delete) => {
+                            queue.retain(|msg: &DisplayMessage| {
+                                msg.channel != delete.channel
+                                    || msg.id.as_ref() != Some(&delete.id)
+                            });
+                            redraw(&term, &columns, &queue);
+                            continue;
+                        }
+                        Event::Timeout(Timeout { channel, userid, .. })
+                        | Event::Ban(Ban { channel, userid, .. }) => {
+                            queue.retain(|msg: &DisplayMessage| {
+                                msg.channel != channel || msg.userid.as_ref() != Some(&userid)
+                            });
+                            redraw(&term, &columns, &queue);
+                            continue;
+                        }
+                        Event::Clear(Clear { channel }) => {
+                            queue.retain(|msg: &DisplayMessage| msg.channel != channel);
+                            redraw(&term, &columns, &queue);
+                            continue;
+                        }
+                        _ => continue,
+                    };
                     columns.draw(&msg, &mut std::io::stdout());
                     queue.push(msg);
                 },
@@ -47,7 +87,7 @@ impl Window {
 
                         let mut stdout = std::io::stdout();
                         for msg in queue.iter() {
-                            columns.draw(&msg, &mut stdout);
+                            columns.draw(msg, &mut stdout);
                         }
                     }
                 }
@@ -56,6 +96,36 @@ impl Window {
     }
 }
 
+// the terminal can't take lines back, so clear it and draw what is left
+fn redraw(term: &console::Term, columns: &Columns<'_>, queue: &streamchat::Queue<DisplayMessage>) {
+    let _ = term.clear_screen();
+    let mut stdout = std::io::stdout();
+    for msg in queue.iter() {
+        columns.draw(msg, &mut stdout);
+    }
+}
+
+fn describe_sub(sub: &Subscription) -> String {
+    let desc = match sub.kind {
+        SubKind::Sub => format!("{} subscribed", sub.name),
+        SubKind::Resub => format!("{} resubscribed for {} months", sub.name, sub.months),
+        SubKind::Gift => format!(
+            "{} gifted a sub to {}",
+            sub.name,
+            sub.recipient
+                .as_ref()
+                .map(String::as_str)
+                .unwrap_or("someone")
+        ),
+        SubKind::MysteryGift => format!("{} gifted {} subs", sub.name, sub.gift_count.unwrap_or(1)),
+    };
+
+    match &sub.data {
+        Some(data) => format!("{}: {}", desc, data),
+        None => desc,
+    }
+}
+
 #[derive(Default, PartialEq)]
 struct Size {
     width: u16,
@@ -79,12 +149,32 @@ impl Size {
 
 struct Nick {
     nick: String,
-    color: twitch::Color,
+    color: streamchat::Color,
 }
 
 struct DisplayMessage {
+    channel: String,
     nick: Nick,
     data: String,
+    id: Option<String>,
+    userid: Option<String>,
+    highlight: bool,
+}
+
+impl DisplayMessage {
+    fn notice(channel: &str, data: String) -> Self {
+        Self {
+            channel: channel.to_string(),
+            nick: Nick {
+                nick: "*".to_string(),
+                color: streamchat::Color::default(),
+            },
+            data,
+            id: None,
+            userid: None,
+            highlight: false,
+        }
+    }
 }
 
 impl From<streamchat::Message> for DisplayMessage {
@@ -94,11 +184,15 @@ impl From<streamchat::Message> for DisplayMessage {
             _ => msg.color,
         };
         Self {
+            channel: msg.channel,
             nick: Nick {
                 nick: msg.name,
                 color,
             },
             data: msg.data,
+            id: msg.id,
+            userid: Some(msg.userid),
+            highlight: false,
         }
     }
 }
@@ -184,7 +278,7 @@ impl<'a> Columns<'a> {
 
     fn draw(&self, msg: &DisplayMessage, writer: &mut impl std::io::Write) {
         let Nick { nick, color } = &msg.nick;
-        let twitch::RGB(r, g, b) = color.clone().into();
+        let Rgb(r, g, b) = color.rgb;
 
         let line_width =
             self.max_size - self.nick_size - self.left_fringe.width() - self.right_fringe.width();
@@ -209,7 +303,7 @@ impl<'a> Columns<'a> {
                     writer,
                     "{: >space$} ",
                     Paint::new(self.left_fringe.display()).fg({
-                        let twitch::RGB(r, g, b) = self.left_fringe.color();
+                        let Rgb(r, g, b) = self.left_fringe.color();
                         Color::RGB(r, g, b)
                     }),
                     space = self.nick_size
@@ -217,13 +311,17 @@ impl<'a> Columns<'a> {
                 .unwrap()
             }
 
-            writer.write_all(line.as_bytes()).unwrap();
+            if msg.highlight {
+                write!(writer, "{}", Paint::new(line).bold()).unwrap();
+            } else {
+                writer.write_all(line.as_bytes()).unwrap();
+            }
             if max != 1 && i < max - 1 {
                 write!(
                     writer,
                     " {: >pad$}",
                     Paint::new(self.right_fringe.display()).fg({
-                        let twitch::RGB(r, g, b) = self.right_fringe.color();
+                        let Rgb(r, g, b) = self.right_fringe.color();
                         Color::RGB(r, g, b)
                     }),
                     pad = (line_width - line.width()) + self.right_fringe.width()
@@ -1,5 +1,7 @@
 use configurable::Configurable;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use streamchat::{filter::Filter, Role};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct Config {
@@ -7,8 +9,42 @@ pub(crate) struct Config {
     // XXX: probably shouldn't do this
     pub oauth_token: String,
     pub limit: usize,
-    pub channel: String,
+    /// older configs had a single `channel`
+    #[serde(alias = "channel", deserialize_with = "one_or_many")]
+    pub channels: Vec<String>,
     pub nick: String,
+    /// another socket, sending length prefixed msgpack instead of json lines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub msgpack_address: Option<String>,
+    /// a websocket, sending json text frames. for browser overlays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub websocket_address: Option<String>,
+    /// serves server-sent events on `GET /events`, for browsers and curl
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sse_address: Option<String>,
+    /// the role each command needs, e.g. `color = "subscriber"`. commands default to everyone
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub commands: HashMap<String, Role>,
+    /// only events matching this are sent to the socket, see `streamchat::filter`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    /// a unix socket, sending the same json lines as `address`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unix_socket: Option<UnixSocket>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct UnixSocket {
+    pub path: String,
+    /// the permissions of the socket file, e.g. `mode = 0o660`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// the numeric user id to own the socket file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    /// the numeric group id to own the socket file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
 }
 
 impl Default for Config {
@@ -17,8 +53,14 @@ impl Default for Config {
             address: "localhost:51002".to_string(),
             oauth_token: String::new(),
             limit: 32,
-            channel: "museun".to_string(),
+            channels: vec!["museun".to_string()],
             nick: "museun".to_string(),
+            msgpack_address: None,
+            websocket_address: None,
+            sse_address: None,
+            commands: HashMap::new(),
+            filter: None,
+            unix_socket: None,
         }
     }
 }
@@ -34,3 +76,17 @@ impl Configurable for Config {
         <Self as configurable::Config>::ensure_dir()
     }
 }
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Repr::deserialize(deserializer)? {
+        Repr::One(channel) => vec![channel],
+        Repr::Many(channels) => channels,
+    })
+}
@@ -1,14 +1,13 @@
 use std::env;
-use std::net::TcpStream;
+use std::io;
 
 use configurable::Configurable;
 
 use streamchat::{
-    twitch::{
-        self, commands::PrivMsg, Client, Error as TwitchError, Message as TwitchMsg, ReadAdapter,
-        UserConfig, RGB,
-    },
-    Message, Transport, Version,
+    daemon::{self, Builder, EventStream, Socket, WebSocket},
+    filter::{Filter, Filtered},
+    twitch::{self, RGB},
+    Encoding, Message, Transport,
 };
 
 mod error;
@@ -20,35 +19,20 @@ use colorconfig::ColorConfig;
 mod color;
 use color::RelativeColor as _;
 
-mod service;
-use service::Service;
-
-mod commands;
-use commands::{CommandProcessor, Response};
-
 mod config;
 use config::Config;
 
-mod transports;
-
-#[inline]
-pub(crate) fn make_timestamp() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .expect("valid system time")
-        .as_millis() as u64
-}
-
-fn handle_color(id: u64, args: &str) -> Option<String> {
+fn handle_color(msg: &Message, args: &str) -> Option<String> {
+    let id = msg.userid.parse().ok()?;
     let mut colors = ColorConfig::load();
     match args.split_terminator(' ').next() {
         Some(color) => {
             let color: twitch::Color = color.parse().unwrap_or_default();
             let rgb = RGB::from(color);
             if rgb.is_dark() {
-                let msg = format!("color {} is too dark", rgb);
-                log::warn!("{}", msg);
-                return Some(msg);
+                let reply = format!("color {} is too dark", rgb);
+                log::warn!("{}", reply);
+                return Some(reply);
             }
             let _ = colors.set(id, rgb);
             Some(format!("setting your color to: {}", rgb))
@@ -61,10 +45,71 @@ fn handle_color(id: u64, args: &str) -> Option<String> {
     }
 }
 
+// quits if the transport couldn't start, otherwise applies the configured filter to it
+fn listen<T>(addr: &str, filter: &Option<Filter>, transport: io::Result<T>) -> Box<dyn Transport>
+where
+    T: Transport + 'static,
+{
+    let transport = match transport {
+        Ok(transport) => transport,
+        Err(err) => {
+            log::error!("cannot listen on {}: {}", addr, err);
+            std::process::exit(1);
+        }
+    };
+    match filter.clone() {
+        Some(filter) => Box::new(Filtered::new(transport, filter)),
+        None => Box::new(transport),
+    }
+}
+
+#[cfg(unix)]
+fn listen_unix(unix: &config::UnixSocket, config: &Config) -> Box<dyn Transport> {
+    let options = daemon::UnixOptions {
+        mode: unix.mode,
+        uid: unix.uid,
+        gid: unix.gid,
+    };
+    let socket = Socket::start_unix(&unix.path, &options, config.limit, Encoding::Json);
+    listen(&unix.path, &config.filter, socket)
+}
+
+#[cfg(not(unix))]
+fn listen_unix(unix: &config::UnixSocket, _config: &Config) -> Box<dyn Transport> {
+    log::error!(
+        "cannot listen on {}: unix sockets aren't supported here",
+        unix.path
+    );
+    std::process::exit(1);
+}
+
 // TODO oauth implicit flow grant
-// TODO make the transport selectable (e.g. provide a trait for this)
 fn main() {
     use configurable::LoadState::*;
+    let mut args = env::args().skip(1);
+    if args.any(|arg| arg == "--print-schema") {
+        use streamchat::schema;
+        let schema = match args.next().as_deref() {
+            None | Some("event") => schema::event(),
+            Some("message") => schema::message(),
+            Some("v1") => schema::v1(),
+            Some("hello") => schema::hello(),
+            Some("reply") => schema::reply(),
+            Some(other) => {
+                eprintln!(
+                    "unknown schema `{}`, expected event, message, v1, hello or reply",
+                    other
+                );
+                std::process::exit(1);
+            }
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema).expect("valid json")
+        );
+        return;
+    }
+
     let config = match Config::load_or_default() {
         Ok(Loaded(config)) => config,
         Ok(Default(config)) => {
@@ -87,60 +132,53 @@ fn main() {
         .start()
         .unwrap();
 
-    log::info!("connecting to: {}", twitch::TWITCH_IRC_ADDRESS);
-    let (read, write) = {
-        let read = TcpStream::connect(twitch::TWITCH_IRC_ADDRESS).expect("connect to twitch");
-        let write = read.try_clone().expect("clone tcpstream");
-        (read, write)
-    };
-    log::info!("opened connection");
-
-    let (read, write) = twitch::sync_adapters(read, write);
-
-    let mut client = Client::new(read, write);
-    let conf = UserConfig::builder()
-        .nick(&config.nick)
-        .token(&config.oauth_token)
-        .tags()
-        .commands()
-        .build()
-        .expect("valid configuration");
-
-    log::info!("registering with nick: {}", conf.nick);
-    client.register(conf).expect("register with twitch");
-
-    let user = match client.wait_for_ready() {
-        Ok(user) => user,
-        Err(TwitchError::InvalidRegistration) => {
-            log::error!("invalid nick/pass. check the configuration");
-            std::process::exit(1);
+    if let Some(filter) = &config.filter {
+        log::info!("only sending events matching: {}", filter);
+    }
+    let mut builder = Builder::new(daemon::Config {
+        nick: config.nick.clone(),
+        oauth_token: config.oauth_token.clone(),
+        channels: config.channels.clone(),
+    })
+    .boxed_transport(listen(
+        &config.address,
+        &config.filter,
+        Socket::start(&config.address, config.limit, Encoding::Json),
+    ))
+    .command("color", handle_color)
+    .custom_color(|msg| {
+        let id = msg.userid.parse().ok()?;
+        ColorConfig::load().get(id).map(Into::into)
+    });
+
+    if let Some(addr) = &config.msgpack_address {
+        let socket = Socket::start(addr, config.limit, Encoding::MessagePack);
+        builder = builder.boxed_transport(listen(addr, &config.filter, socket));
+    }
+    if let Some(addr) = &config.websocket_address {
+        let socket = WebSocket::start(addr, config.limit);
+        builder = builder.boxed_transport(listen(addr, &config.filter, socket));
+    }
+    if let Some(addr) = &config.sse_address {
+        let stream = EventStream::start(addr, config.limit);
+        builder = builder.boxed_transport(listen(addr, &config.filter, stream));
+    }
+    if let Some(unix) = &config.unix_socket {
+        builder = builder.boxed_transport(listen_unix(unix, &config));
+    }
+    for (command, role) in &config.commands {
+        builder = builder.require(command, *role);
+    }
+
+    match builder.run() {
+        Err(err) if err.is_fatal() => {
+            log::error!("{}. check the configuration", err);
+            std::process::exit(1)
         }
         Err(err) => {
-            log::error!("cannot complete the connection: {}", err);
-            std::process::exit(1);
+            log::error!("error running service: {}", err);
+            std::process::exit(1)
         }
-    };
-
-    log::info!(
-        "connected with {} ({}).",
-        user.display_name.expect("get our display name"),
-        user.user_id
-    );
-
-    let channel = format!("#{}", config.channel);
-    client.writer().join(channel.clone()).expect("join channel");
-    log::info!("joined: {}", channel);
-
-    let mut processor = CommandProcessor::default();
-    processor.add("color", handle_color);
-
-    let socket = transports::Socket::start(&config.address, config.limit);
-    let transports: Vec<Box<dyn Transport>> = vec![
-        Box::new(socket), // socket transport
-    ];
-
-    if let Err(err) = Service::new(client, transports, processor).run() {
-        log::error!("error running service: {}", err);
-        std::process::exit(1)
+        Ok(..) => {}
     }
 }
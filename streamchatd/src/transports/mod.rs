@@ -1,7 +0,0 @@
-mod socket;
-pub use self::socket::Socket;
-
-// TODO this
-// mod websocket;
-// pub use self::websocket::{Config as WsConfig, Error as WsError,
-// WebsocketServer};